@@ -1,4 +1,5 @@
 use crate::code_block;
+use crate::globals::*;
 use crate::space::*;
 use crate::Page;
 
@@ -19,7 +20,7 @@ pub struct AllocationData {
     page: Option<*mut Page>,
 }
 impl AllocationData {
-    pub fn new() -> Self {
+    pub(crate) fn new() -> Self {
         Self {
             data_start: None,
             data_end: None,
@@ -44,6 +45,41 @@ impl AllocationData {
     }
     //////////////////////////////////////////////////////////
     // Generated data
+    /// Reads the free bit from the left code block,
+    /// with ``free_map`` from the free map of the page
+    pub fn is_free(&self) -> bool {
+        #[cfg(not(feature = "free_map"))]
+        {
+            code_block::is_free(self.data_start())
+        }
+        #[cfg(feature = "free_map")]
+        unsafe {
+            (*self.page()).is_free(self.data_start())
+        }
+    }
+    /// Marks the block as free or used in both code blocks,
+    /// with ``free_map`` in the free map of the page
+    pub(crate) unsafe fn set_free(&mut self, free: bool) {
+        #[cfg(not(feature = "free_map"))]
+        {
+            code_block::set_free(self.data_start(), free);
+            self.copy_code_block_to_end();
+        }
+        #[cfg(feature = "free_map")]
+        (*self.page()).set_free_in_map(self.data_start(), free);
+    }
+    /// Both code blocks of a block are equal unless they were overwritten
+    pub fn code_blocks_match(&self) -> bool {
+        let code_block_size = self.code_block_size();
+        unsafe {
+            let left = core::slice::from_raw_parts(self.data_start(), code_block_size);
+            let right = core::slice::from_raw_parts(
+                self.data_end().add(1).sub(code_block_size),
+                code_block_size,
+            );
+            left == right
+        }
+    }
     #[inline]
     pub fn calculate_data_size(&self) -> usize {
         if let Some(data_start) = self.data_start {
@@ -88,6 +124,24 @@ impl AllocationData {
             }
         }
     }
+    /// The location where the next pointer of a free space is stored.
+    /// This is the first byte of ``space`` right behind the left code block.
+    /// If the space pointer is not cached, it is derived from ``data_start``
+    /// and the code block size.
+    /// Useful to inspect the raw next pointer while debugging the free list.
+    #[allow(dead_code)]
+    pub fn next_pointer_location(&self) -> *mut NextPointerType {
+        unsafe {
+            if self.space.is_some() {
+                return self.space.ptr() as *mut NextPointerType;
+            }
+            let code_block_size = match self.code_block_size {
+                Some(size) => size,
+                None => code_block::get_block_size(self.data_start(), false),
+            };
+            self.data_start().add(code_block_size) as *mut NextPointerType
+        }
+    }
     //////////////////////////////////////////////////////////////
     // setter
     pub fn set_data_start(&mut self, data: *mut u8) {
@@ -183,7 +237,7 @@ impl AllocationData {
     }
 
     /// Copies a code block from the beginning of space to the end of space
-    pub unsafe fn copy_code_block_to_end(&mut self) {
+    pub(crate) unsafe fn copy_code_block_to_end(&mut self) {
         #[cfg(feature = "consistency-checks")]
         {
             assert!(self.code_block_size() > 0);
@@ -211,7 +265,7 @@ impl AllocationData {
     /// determined by ``is_free``.
     /// Allocation cache for ``space`` and ``space size is updated``
     /// The ``next`` pointer is also written at the correct location
-    pub unsafe fn write_data_size_code_blocks(&mut self, is_free: bool) {
+    pub(crate) unsafe fn write_data_size_code_blocks(&mut self, is_free: bool) {
         let code_block_size = code_block::generate_code_block_for_internal_size(
             self.data_start(),
             self.calculate_data_size(),
@@ -222,6 +276,8 @@ impl AllocationData {
         self.space.cache_size_from_code_block();
         self.set_data_end(self.data_start().add(self.calculate_data_size()).sub(1));
         self.copy_code_block_to_end();
+        #[cfg(feature = "free_map")]
+        (*self.page()).set_free_in_map(self.data_start(), is_free);
         #[cfg(feature = "consistency-checks")]
         {
             let (right_block_size, _) = code_block::read_from_right(self.data_end());
@@ -233,7 +289,7 @@ impl AllocationData {
     /// determined by ``is_free``.
     /// Allocation cache for ``data_end`` and ``space`` is updated (allocation might
     /// shrink if the code block get smaller)
-    pub unsafe fn write_space_size_code_blocks(&mut self, is_free: bool) {
+    pub(crate) unsafe fn write_space_size_code_blocks(&mut self, is_free: bool) {
         code_block::generate_code_block_for_payload_size(self, is_free);
         self.set_data_end(
             self.space
@@ -243,6 +299,8 @@ impl AllocationData {
                 .sub(1),
         );
         self.copy_code_block_to_end();
+        #[cfg(feature = "free_map")]
+        (*self.page()).set_free_in_map(self.data_start(), is_free);
         #[cfg(feature = "consistency-checks")]
         {
             let (right_block_size, _) = code_block::read_from_right(self.data_end());
@@ -253,6 +311,7 @@ impl AllocationData {
     //////////////////////////////////////////////////////////
     // Consistency checks
     #[inline]
+    #[cfg_attr(not(feature = "consistency-checks"), allow(unused_variables))]
     pub fn check_data_size(&self, min: usize, max: usize) {
         #[cfg(feature = "consistency-checks")]
         {
@@ -269,27 +328,21 @@ impl AllocationData {
         }
     }
     #[inline]
+    #[cfg_attr(not(feature = "consistency-checks"), allow(unused_variables))]
     pub fn check_left_free(&self, expected: bool) {
         #[cfg(feature = "consistency-checks")]
         {
             //if we know the data start check the block
-            if self.data_start.is_some() {
-                if code_block::is_free(self.data_start()) != expected {
-                    dbg!(code_block::is_free(self.data_start()));
-                    dbg!(expected);
-                    panic!("Free bit was not set correctly")
-                }
-            }
+            let mut alloc_data = *self;
             // else expect to know the space pointer and read from right
-            else {
-                unsafe {
-                    let block = code_block::read_from_right(self.space.ptr().sub(1)).1;
-                    if code_block::is_free(block) != expected {
-                        dbg!(code_block::is_free(block));
-                        dbg!(expected);
-                        panic!("Free bit was not set correctly")
-                    }
-                }
+            if self.data_start.is_none() {
+                let block = unsafe { code_block::read_from_right(self.space.ptr().sub(1)).1 };
+                alloc_data.set_data_start(block);
+            }
+            if alloc_data.is_free() != expected {
+                dbg!(alloc_data.is_free());
+                dbg!(expected);
+                panic!("Free bit was not set correctly")
             }
         }
     }
@@ -329,12 +382,15 @@ impl AllocationData {
                     panic!("space pointer is not at expected position");
                 }
                 // check both free bits are consistent
-                let left_free = code_block::is_free(self.data_start());
-                let right_free = code_block::is_free(self.calculate_right_code_block());
-                if left_free != right_free {
-                    dbg!(left_free);
-                    dbg!(right_free);
-                    panic!("free flags inconsistent")
+                #[cfg(not(feature = "free_map"))]
+                {
+                    let left_free = code_block::is_free(self.data_start());
+                    let right_free = code_block::is_free(self.calculate_right_code_block());
+                    if left_free != right_free {
+                        dbg!(left_free);
+                        dbg!(right_free);
+                        panic!("free flags inconsistent")
+                    }
                 }
                 // check both codeblocks encode the same size
                 let left_size = code_block::read_from_left(self.data_start());
@@ -360,3 +416,31 @@ impl AllocationData {
         }
     }
 }
+
+// the free map needs a page
+#[cfg(not(feature = "free_map"))]
+#[test]
+fn test_next_pointer_location() {
+    // blocks with a single byte and a multi byte code block
+    for &size in [16, 1024].iter() {
+        let mut memory = [0u8; 1024];
+        let start = memory.as_mut_ptr();
+        unsafe {
+            let mut alloc = AllocationData::new();
+            alloc.set_data_start(start);
+            alloc.set_data_end(start.add(size - 1));
+            alloc.write_data_size_code_blocks(true);
+            let mut next = Space::new();
+            next.set_ptr(start.add(8));
+            alloc.space.set_next(Some(next));
+            alloc.space.write_next(start).unwrap();
+            // a view that only knows the start of the block
+            let mut uncached = AllocationData::new();
+            uncached.set_data_start(start);
+            let location = uncached.next_pointer_location();
+            assert_eq!(location, alloc.next_pointer_location());
+            assert_eq!(location as *mut u8, alloc.space.ptr());
+            assert_eq!(location.read_unaligned(), 8);
+        }
+    }
+}
@@ -0,0 +1,89 @@
+//! Lets collections use Mara without making it the global allocator,
+//! e.g. ``Vec::new_in(&mara)``.
+//! The returned slices are as long as the usable size of their block.
+
+use crate::allocation_data::AllocationData;
+use crate::Mara;
+use core::alloc::{AllocError, Allocator, Layout};
+use core::ptr::NonNull;
+
+unsafe impl Allocator for &Mara {
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        if layout.size() == 0 {
+            return self.allocate_slice(layout).ok_or(AllocError);
+        }
+        let ptr = {
+            let _lock = self.lock.lock();
+            let mut allocation_data = AllocationData::new();
+            allocation_data.space.set_size(layout.size());
+            self.page_list()
+                .aligned_new(&mut allocation_data, layout.align());
+            if !allocation_data.space.is_some() {
+                return Err(AllocError);
+            }
+            allocation_data.space.ptr()
+        };
+        Ok(self.usable_slice(ptr))
+    }
+
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        if layout.size() != 0 {
+            self.dynamic_delete(ptr.as_ptr());
+        }
+    }
+
+    unsafe fn grow(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        if old_layout.size() != 0 && ptr.as_ptr().align_offset(new_layout.align()) == 0 {
+            let _lock = self.lock.lock();
+            let page_list = self.page_list();
+            if new_layout.size() <= page_list.usable_size(ptr.as_ptr())
+                || page_list.grow_in_place(ptr.as_ptr(), new_layout.size())
+            {
+                return Ok(self.usable_slice(ptr.as_ptr()));
+            }
+        }
+        let new_ptr = self.allocate(new_layout)?;
+        core::ptr::copy_nonoverlapping(
+            ptr.as_ptr(),
+            new_ptr.as_ptr() as *mut u8,
+            old_layout.size(),
+        );
+        self.deallocate(ptr, old_layout);
+        Ok(new_ptr)
+    }
+
+    unsafe fn shrink(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        if new_layout.size() != 0 && ptr.as_ptr().align_offset(new_layout.align()) == 0 {
+            let _lock = self.lock.lock();
+            self.page_list()
+                .shrink_in_place(ptr.as_ptr(), new_layout.size());
+            return Ok(self.usable_slice(ptr.as_ptr()));
+        }
+        let new_ptr = self.allocate(new_layout)?;
+        core::ptr::copy_nonoverlapping(
+            ptr.as_ptr(),
+            new_ptr.as_ptr() as *mut u8,
+            new_layout.size(),
+        );
+        self.deallocate(ptr, old_layout);
+        Ok(new_ptr)
+    }
+}
+
+impl Mara {
+    /// The whole block of ``ptr`` as slice
+    fn usable_slice(&self, ptr: *mut u8) -> NonNull<[u8]> {
+        let len = self.page_list().usable_size(ptr);
+        NonNull::slice_from_raw_parts(NonNull::new(ptr).expect("Mara: null block"), len)
+    }
+}
@@ -0,0 +1,27 @@
+use crate::AllocationData;
+
+/// Describes a block in a page as the user sees it
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct BlockInfo {
+    /// The pointer to the space of the block.
+    /// This is the pointer ``dynamic_new`` returns for used blocks.
+    pub ptr: *mut u8,
+    /// Size of the space in bytes (without code blocks)
+    pub size: usize,
+    /// Size of each of the two code blocks around the space
+    pub code_block_size: usize,
+    /// True if the block is in the bucket list
+    pub free: bool,
+}
+
+impl From<&AllocationData> for BlockInfo {
+    /// Expects the code blocks of ``alloc_data`` to be cached
+    fn from(alloc_data: &AllocationData) -> Self {
+        Self {
+            ptr: alloc_data.space.ptr(),
+            size: alloc_data.space.size(),
+            code_block_size: alloc_data.code_block_size(),
+            free: alloc_data.is_free(),
+        }
+    }
+}
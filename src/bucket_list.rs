@@ -1,60 +1,175 @@
 use crate::code_block;
+use crate::error::MaraError;
 use crate::globals::*;
 use crate::space::Space;
+#[cfg(feature = "statistic")]
+use crate::statistic::{SearchStats, Stopwatch, TimingBreakdown};
 use crate::Page;
+use core::mem::size_of;
+
+// every bucket needs a bit in ``BucketList::occupied``
+const _: () = assert!(BUCKET_LIST_SIZE <= 32);
+
+/// Index of the bucket for the sizes right above ``LAST_LINEAR_4_SCALING``
+const FIRST_LINEAR_16_BUCKET: usize = (LAST_LINEAR_4_SCALING - 1) / 4 + 1;
+/// Index of the bucket for the sizes right above ``LAST_LINEAR_16_SCALING``
+const FIRST_LOG2_BUCKET: usize =
+    FIRST_LINEAR_16_BUCKET + (LAST_LINEAR_16_SCALING - LAST_LINEAR_4_SCALING - 1) / 16 + 1;
+
+/// Where a freed space is added to its bucket
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum InsertionPolicy {
+    /// In front of the bucket, the most recently freed space is used first.
+    /// Its memory is likely still cached.
+    Lifo,
+    /// At the end of the bucket, the oldest freed space is used first.
+    /// All spaces of a size age evenly.
+    Fifo,
+    /// Sorted by address, the space with the lowest address is used first.
+    /// Allocations are packed towards the start of the page, but every
+    /// insertion walks the bucket.
+    AddressOrdered,
+}
+
+/// How a free space is picked from a bucket
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum FitPolicy {
+    /// The first space of the bucket that is large enough.
+    /// Only the last bucket, which holds all large sizes, is searched for
+    /// the smallest fitting space.
+    FirstFit,
+    /// The smallest space of the bucket that is large enough.
+    /// The whole bucket is walked, the remainders of the splits are smaller.
+    BestFit,
+}
 
 pub struct BucketList {
     /// The array with the information of free sections
     /// The space pointed to at the given index is the first one of the size class.
     /// Each index represent another size class. Increasing indices represent increasing size classes.
     bucket_list: [*mut u8; BUCKET_LIST_SIZE],
+    /// The last space of each bucket, null if the bucket is empty
+    tails: [*mut u8; BUCKET_LIST_SIZE],
+    /// Bit ``i`` is set if bucket ``i`` holds a space
+    occupied: u32,
+    /// No space in the list is larger, see ``largest_free_size``
+    largest_free: usize,
+    insertion_policy: InsertionPolicy,
+    fit_policy: FitPolicy,
     page: *mut Page,
+    /// Steps of the searches in ``get_free_space``
+    #[cfg(feature = "statistic")]
+    search_stats: core::cell::Cell<SearchStats>,
+    /// Current number of spaces in each bucket
+    #[cfg(feature = "statistic")]
+    chain_lengths: [usize; BUCKET_LIST_SIZE],
+    /// Largest number of spaces each bucket ever held
+    #[cfg(feature = "statistic")]
+    max_chain_lengths: [usize; BUCKET_LIST_SIZE],
+    /// Time spent in the bucket list and in the merges of the page
+    #[cfg(feature = "statistic")]
+    timing: core::cell::Cell<TimingBreakdown>,
+    /// Bit ``i`` is set if a block of bucket ``i`` was ever allocated or freed
+    #[cfg(feature = "statistic")]
+    touched_buckets: u64,
+}
+/// Follows the next pointers of a bucket.
+/// A malformed list ends the iteration early: it stops at a space outside
+/// of the page and after as many spaces as fit in the page.
+pub struct BucketIter {
+    space: Option<Space>,
+    start_of_page: *const u8,
+    end_of_page: *const u8,
+    remaining: usize,
+}
+
+impl Iterator for BucketIter {
+    type Item = Space;
+    fn next(&mut self) -> Option<Space> {
+        let space = self.space.take()?;
+        let ptr = space.ptr() as *const u8;
+        if self.remaining == 0
+            || ptr <= self.start_of_page
+            || ptr as usize + size_of::<NextPointerType>() > self.end_of_page as usize
+        {
+            return None;
+        }
+        self.remaining -= 1;
+        self.space = space.read_next(self.start_of_page);
+        Some(space)
+    }
 }
+
 impl BucketList {
     /// **index**:
     /// start index to search. The returned index will be greater or equal
     /// to this index.
     ///
+    /// **budget**:
+    /// remaining search steps. Every scanned index costs one step.
+    ///
     /// Returns a bucket index with a non null entry.
     /// The index will always be >= the given index.
+    /// If the budget is exhausted the last scanned index is returned.
+    /// The next non empty bucket is looked up in the ``occupied`` bitmap,
+    /// the skipped indices are charged as if they were scanned.
     #[inline]
-    fn find_non_empty_bucket(&self, mut index: usize) -> usize {
+    fn find_non_empty_bucket(&self, mut index: usize, budget: &mut usize) -> usize {
         #[cfg(feature = "consistency-checks")]
         {
             assert!(index < BUCKET_LIST_SIZE);
+            self.check_occupied();
         }
-        while self.get(index).is_none() {
-            if index < BUCKET_LIST_SIZE - 1 {
-                index += 1;
-            } else {
-                break;
-            }
-        }
+        let candidates = self.occupied >> index;
+        let target = if candidates == 0 {
+            BUCKET_LIST_SIZE - 1
+        } else {
+            index + candidates.trailing_zeros() as usize
+        };
+        let steps = (target - index).min(*budget);
+        *budget -= steps;
+        index += steps;
         #[cfg(feature = "consistency-checks")]
         {
-            assert!(!self.get(index).is_none() || index == BUCKET_LIST_SIZE - 1);
+            assert!(
+                self.get(index).is_some() || index == BUCKET_LIST_SIZE - 1 || *budget == 0
+            );
         }
         index
     }
     /// Greedy search in the bucket.
     /// Returns the first element that matches the size and ignores the actual size.
+    /// The last bucket holds all sizes above ``LARGEST_BUCKET_SIZE``, there
+    /// the smallest fitting element is returned instead, so that a small request
+    /// does not split the largest free space of the page.
+    /// With ``FitPolicy::BestFit`` every bucket is searched for the smallest one.
     /// None if no fitting space is found in the bucket,
     /// else Some(free_space) with a size greater than byte.
     /// As the name implies only the bucket with the given index is searched
+    /// Every visited space costs one step of the ``budget``, None is returned
+    /// if it is exhausted.
     #[inline]
     unsafe fn find_fitting_space_in_bucket(
         &self,
         minimum_size: usize,
         index: usize,
+        budget: &mut usize,
     ) -> Option<Space> {
         #[cfg(feature = "consistency-checks")]
         {
             assert!(minimum_size > 0);
             assert!(index < BUCKET_LIST_SIZE);
         }
+        if index == BUCKET_LIST_SIZE - 1 || self.fit_policy == FitPolicy::BestFit {
+            return self.find_smallest_fitting_space(minimum_size, index, budget);
+        }
         let mut space = self.get(index);
         // Search to the end of the bucket
         while let Some(unwrapped) = space {
+            if *budget == 0 {
+                return None;
+            }
+            *budget -= 1;
             // Check if the adjacent code block encodes a fitting size
             if code_block::read_from_right(unwrapped.ptr().sub(1)).0 >= minimum_size {
                 break;
@@ -64,29 +179,102 @@ impl BucketList {
         self.check_found(&space, minimum_size);
         space
     }
+    /// Best fit search in the bucket.
+    /// Walks the whole bucket unless a space of exactly ``minimum_size`` is found.
+    /// Every visited space costs one step of the ``budget``, None is returned
+    /// if it is exhausted.
+    #[inline]
+    unsafe fn find_smallest_fitting_space(
+        &self,
+        minimum_size: usize,
+        index: usize,
+        budget: &mut usize,
+    ) -> Option<Space> {
+        let mut best: Option<(Space, usize)> = None;
+        let mut space = self.get(index);
+        while let Some(unwrapped) = space {
+            if *budget == 0 {
+                return None;
+            }
+            *budget -= 1;
+            let size = code_block::read_from_right(unwrapped.ptr().sub(1)).0;
+            let smaller = match best {
+                Some((_, best_size)) => size < best_size,
+                None => true,
+            };
+            if size >= minimum_size && smaller {
+                best = Some((unwrapped, size));
+                if size == minimum_size {
+                    break;
+                }
+            }
+            space = unwrapped.read_next((*self.page).start_of_page());
+        }
+        let space = best.map(|(space, _)| space);
+        self.check_found(&space, minimum_size);
+        space
+    }
     /// Initializes a new bucket list.
     /// All entries are zeroed
     #[inline]
     pub fn init(&mut self, page: *mut Page) {
         self.page = page;
+        #[cfg(feature = "statistic")]
+        {
+            // the bucket list lives in uninitialized memory, so nothing may be dropped
+            unsafe {
+                core::ptr::write(&mut self.search_stats, Default::default());
+                core::ptr::write(&mut self.timing, Default::default());
+            }
+            self.max_chain_lengths = [0; BUCKET_LIST_SIZE];
+            self.touched_buckets = 0;
+        }
+        self.clear();
+        self.insertion_policy = InsertionPolicy::Lifo;
+        self.fit_policy = FitPolicy::FirstFit;
+    }
+    /// Empties all buckets without touching the spaces they point to.
+    /// The insertion policy and the statistic peaks are kept.
+    pub fn clear(&mut self) {
+        #[cfg(feature = "statistic")]
+        {
+            self.chain_lengths = [0; BUCKET_LIST_SIZE];
+        }
         for i in 0..BUCKET_LIST_SIZE {
             self.bucket_list[i] = core::ptr::null_mut();
+            self.tails[i] = core::ptr::null_mut();
         }
+        self.occupied = 0;
+        self.largest_free = 0;
     }
     /// Searches all appropriate buckets for a fitting size
     /// The list is not altered.
-    /// None if no space was found.
+    /// None if no space was found or the search took more steps than ``budget``
+    /// allows (see ``find_non_empty_bucket`` and ``find_fitting_space_in_bucket``).
     #[inline]
-    pub unsafe fn get_free_space(&self, minimum_size: usize) -> Option<Space> {
+    pub unsafe fn get_free_space(
+        &self,
+        minimum_size: usize,
+        budget: &mut usize,
+    ) -> Option<Space> {
         #[cfg(feature = "consistency-checks")]
         {
             assert!(minimum_size > 0);
         }
+        #[cfg(feature = "statistic")]
+        let initial_budget = *budget;
+        #[cfg(feature = "statistic")]
+        let stopwatch = Stopwatch::start();
         let mut bucket_index = Self::lookup_bucket(minimum_size);
         let space;
         loop {
-            bucket_index = self.find_non_empty_bucket(bucket_index);
-            match self.find_fitting_space_in_bucket(minimum_size, bucket_index) {
+            bucket_index = self.find_non_empty_bucket(bucket_index, budget);
+            match self.find_fitting_space_in_bucket(minimum_size, bucket_index, budget) {
+                // the last bucket was searched, there is no larger one
+                None if bucket_index == BUCKET_LIST_SIZE - 1 || *budget == 0 => {
+                    space = None;
+                    break;
+                }
                 None => bucket_index += 1,
                 Some(mut fiting) => {
                     fiting.cache_size_from_code_block();
@@ -95,34 +283,107 @@ impl BucketList {
                     break;
                 }
             }
-            if bucket_index == (BUCKET_LIST_SIZE) {
-                space = None;
-                break;
-            }
+        }
+        #[cfg(feature = "statistic")]
+        {
+            let mut search_stats = self.search_stats.get();
+            search_stats.record(initial_budget - *budget);
+            self.search_stats.set(search_stats);
+            self.record_time(|timing| timing.search += stopwatch.elapsed());
         }
         self.check_found(&space, minimum_size);
         space
     }
+    /// The accumulated steps of all searches in this bucket list
+    #[cfg(feature = "statistic")]
+    pub fn search_stats(&self) -> SearchStats {
+        self.search_stats.get()
+    }
+    /// Replaces the accumulated search steps, e.g. to reset them
+    #[cfg(feature = "statistic")]
+    pub fn set_search_stats(&self, search_stats: SearchStats) {
+        self.search_stats.set(search_stats)
+    }
+    /// The largest number of spaces each bucket held so far
+    #[cfg(feature = "statistic")]
+    pub fn max_chain_lengths(&self) -> [usize; BUCKET_LIST_SIZE] {
+        self.max_chain_lengths
+    }
+    /// Sets the maximal chain lengths to the current lengths
+    #[cfg(feature = "statistic")]
+    pub fn reset_max_chain_lengths(&mut self) {
+        self.max_chain_lengths = self.chain_lengths;
+    }
+    /// Marks the bucket of ``size`` as touched by an allocation or a free
+    #[cfg(feature = "statistic")]
+    #[inline]
+    pub fn record_touched(&mut self, size: usize) {
+        self.touched_buckets |= 1 << Self::lookup_bucket(size);
+    }
+    /// The buckets that were ever touched, bit ``i`` stands for bucket ``i``
+    #[cfg(feature = "statistic")]
+    pub fn touched_buckets(&self) -> u64 {
+        self.touched_buckets
+    }
+    /// The accumulated time of the searches, bucket updates and merges
+    #[cfg(feature = "statistic")]
+    pub fn timing(&self) -> TimingBreakdown {
+        self.timing.get()
+    }
+    /// Adds a measured duration to the timing breakdown
+    #[cfg(feature = "statistic")]
+    pub fn record_time(&self, record: impl FnOnce(&mut TimingBreakdown)) {
+        let mut timing = self.timing.get();
+        record(&mut timing);
+        self.timing.set(timing);
+    }
     /// removes ``space`` from the bucket list
     /// panics if it was not found
-    pub unsafe fn remove(&mut self, space: &Space) {
-        let (in_list, predecessor) = self.is_in_list(&space);
+    /// Fails if the next pointer of the predecessor cannot be written.
+    /// The list is unchanged in that case.
+    pub unsafe fn remove(&mut self, space: &Space) -> Result<(), MaraError> {
+        #[cfg(feature = "statistic")]
+        let stopwatch = Stopwatch::start();
+        let result = self.unlink(space);
+        #[cfg(feature = "statistic")]
+        self.record_time(|timing| timing.bucket_update += stopwatch.elapsed());
+        result
+    }
+    /// Does the work of ``remove``
+    unsafe fn unlink(&mut self, space: &Space) -> Result<(), MaraError> {
+        // the bucket is looked up with the cached size
+        space.check_cached_size();
+        let (in_list, predecessor) = self.is_in_list(space);
         if in_list {
+            let bucket = Self::lookup_bucket(space.size());
             // alloc data is not the first element in the bucket
             if let Some(mut predecessor) = predecessor {
                 predecessor.set_next(space.next());
-                predecessor.write_next((*self.page).start_of_page())
+                predecessor.write_next((*self.page).start_of_page())?;
+                if space.next().is_none() {
+                    self.tails[bucket] = predecessor.ptr();
+                }
             }
             // alloc data is the first element in the bucket
             else {
                 match space.next() {
-                    Some(next) => self.bucket_list[Self::lookup_bucket(space.size())] = next.ptr(),
+                    Some(next) => self.bucket_list[bucket] = next.ptr(),
                     None => {
-                        self.bucket_list[Self::lookup_bucket(space.size())] = core::ptr::null_mut()
+                        self.bucket_list[bucket] = core::ptr::null_mut();
+                        self.tails[bucket] = core::ptr::null_mut();
+                        self.occupied &= !(1 << bucket);
                     }
                 }
             }
+            if space.size() >= self.largest_free {
+                self.largest_free = self.largest_free_bound();
+            }
+            #[cfg(feature = "statistic")]
+            {
+                self.chain_lengths[Self::lookup_bucket(space.size())] -= 1;
+            }
             self.check_in_list(space, false);
+            Ok(())
         } else {
             panic!("Allocation not found");
         }
@@ -140,6 +401,74 @@ impl BucketList {
             }
         }
     }
+    /// Selects where freed spaces are added to their bucket
+    pub fn set_insertion_policy(&mut self, insertion_policy: InsertionPolicy) {
+        self.insertion_policy = insertion_policy;
+    }
+    pub fn insertion_policy(&self) -> InsertionPolicy {
+        self.insertion_policy
+    }
+    /// Selects how a free space is picked from a bucket
+    pub fn set_fit_policy(&mut self, fit_policy: FitPolicy) {
+        self.fit_policy = fit_policy;
+    }
+    pub fn fit_policy(&self) -> FitPolicy {
+        self.fit_policy
+    }
+    /// No space in the list is larger than this size, so larger requests
+    /// fail without a search.
+    /// It is exact after insertions. After the largest space was removed
+    /// it is the largest size of the highest non empty bucket, only the last
+    /// bucket, which has no upper size, is walked.
+    pub fn largest_free_size(&self) -> usize {
+        self.largest_free
+    }
+    /// An upper bound of the size of the largest space in the list
+    unsafe fn largest_free_bound(&self) -> usize {
+        if self.occupied == 0 {
+            return 0;
+        }
+        let highest = 31 - self.occupied.leading_zeros() as usize;
+        if highest == BUCKET_LIST_SIZE - 1 {
+            self.iter_bucket(highest)
+                .map(|space| space.size_from_memory())
+                .max()
+                .unwrap_or(0)
+        } else {
+            Self::bucket_max_size(highest)
+        }
+    }
+    /// The largest size that is stored in the bucket with the given index.
+    /// The last bucket holds all larger sizes, ``usize::MAX`` is returned for it.
+    pub fn bucket_max_size(index: usize) -> usize {
+        if index < FIRST_LINEAR_16_BUCKET {
+            (index + 1) * 4
+        } else if index < FIRST_LOG2_BUCKET {
+            LAST_LINEAR_4_SCALING + (index + 1 - FIRST_LINEAR_16_BUCKET) * 16
+        } else if index < BUCKET_LIST_SIZE - 1 {
+            LAST_LINEAR_16_SCALING << (index + 1 - FIRST_LOG2_BUCKET)
+        } else {
+            usize::MAX
+        }
+    }
+    /// The spaces in the bucket with the given index in list order.
+    /// Yields at most ``max_chain_length`` spaces, so a cycle cannot
+    /// loop forever.
+    pub fn iter_bucket(&self, index: usize) -> BucketIter {
+        unsafe {
+            BucketIter {
+                space: self.get(index),
+                start_of_page: (*self.page).start_of_page(),
+                end_of_page: (*self.page).end_of_page(),
+                remaining: self.max_chain_length(),
+            }
+        }
+    }
+    /// Every free space needs a minimum amount of bytes,
+    /// so a longer chain has to contain a cycle
+    pub fn max_chain_length(&self) -> usize {
+        unsafe { (*self.page).page_size() / SMALLEST_POSSIBLE_FREE_SPACE }
+    }
     /// The space from the bucket that matches ``size``
     #[inline]
     pub fn first_for_size(&self, size: usize) -> Option<Space> {
@@ -153,16 +482,86 @@ impl BucketList {
         }
     }
     /// Adds ``space`` to the bucket list.
-    /// It will be the new first space for the matching bucket.
-    /// The old first will be the new next of ``space``
-    pub unsafe fn insert(&mut self, space: &mut Space) {
+    /// Depending on the insertion policy it will be the new first or last
+    /// space of the matching bucket or is placed by its address.
+    /// Fails if a next pointer cannot be written.
+    /// The list is unchanged in that case.
+    pub unsafe fn insert(&mut self, space: &mut Space) -> Result<(), MaraError> {
+        #[cfg(feature = "statistic")]
+        let stopwatch = Stopwatch::start();
+        let result = self.link(space);
+        #[cfg(feature = "statistic")]
+        self.record_time(|timing| timing.bucket_update += stopwatch.elapsed());
+        result
+    }
+    /// Does the work of ``insert``
+    unsafe fn link(&mut self, space: &mut Space) -> Result<(), MaraError> {
+        // the bucket is chosen by the cached size
+        space.check_cached_size();
         self.check_in_list(space, false);
 
-        space.set_next(self.first_for_size(space.size()));
-        space.write_next((*self.page).start_of_page());
-        self.bucket_list[Self::lookup_bucket(space.size())] = space.ptr();
+        let bucket = Self::lookup_bucket(space.size());
+        let start_of_page = (*self.page).start_of_page();
+        match self.insertion_policy {
+            // the old first will be the new next of ``space``
+            InsertionPolicy::Lifo => {
+                space.set_next(self.get(bucket));
+                space.write_next(start_of_page)?;
+                if self.tails[bucket].is_null() {
+                    self.tails[bucket] = space.ptr();
+                }
+                self.bucket_list[bucket] = space.ptr();
+            }
+            // ``space`` will be the new next of the old last
+            InsertionPolicy::Fifo => {
+                space.set_next(None);
+                space.write_next(start_of_page)?;
+                if self.tails[bucket].is_null() {
+                    self.bucket_list[bucket] = space.ptr();
+                } else {
+                    let mut tail = Space::new();
+                    tail.set_ptr(self.tails[bucket]);
+                    tail.set_next(Some(*space));
+                    tail.write_next(start_of_page)?;
+                }
+                self.tails[bucket] = space.ptr();
+            }
+            // ``space`` will be the new next of the last space with a lower address
+            InsertionPolicy::AddressOrdered => {
+                let predecessor = self
+                    .iter_bucket(bucket)
+                    .take_while(|other| (other.ptr() as usize) < space.ptr() as usize)
+                    .last();
+                match predecessor {
+                    None => {
+                        space.set_next(self.get(bucket));
+                        space.write_next(start_of_page)?;
+                        self.bucket_list[bucket] = space.ptr();
+                    }
+                    Some(mut predecessor) => {
+                        space.set_next(predecessor.read_next(start_of_page));
+                        space.write_next(start_of_page)?;
+                        predecessor.set_next(Some(*space));
+                        predecessor.write_next(start_of_page)?;
+                    }
+                }
+                if space.next().is_none() {
+                    self.tails[bucket] = space.ptr();
+                }
+            }
+        }
+        self.occupied |= 1 << bucket;
+        self.largest_free = self.largest_free.max(space.size());
+        #[cfg(feature = "statistic")]
+        {
+            self.chain_lengths[bucket] += 1;
+            if self.chain_lengths[bucket] > self.max_chain_lengths[bucket] {
+                self.max_chain_lengths[bucket] = self.chain_lengths[bucket];
+            }
+        }
 
         self.check_in_list(space, true);
+        Ok(())
     }
 
     /// Get the correct index in the bucket list for a block with the given
@@ -173,16 +572,13 @@ impl BucketList {
             assert!(size > 0);
         }
         if size <= LAST_LINEAR_4_SCALING {
-            return (size - 1) / 4;
+            (size - 1) / 4
         } else if size <= LAST_LINEAR_16_SCALING {
-            return Self::lookup_bucket(LAST_LINEAR_4_SCALING)
-                + 1
-                + (size - LAST_LINEAR_4_SCALING - 1) / 16;
+            FIRST_LINEAR_16_BUCKET + (size - LAST_LINEAR_4_SCALING - 1) / 16
         } else if size <= LARGEST_BUCKET_SIZE {
-            return Self::lookup_bucket(LAST_LINEAR_16_SCALING) + 1 + log2(size - 1)
-                - log2(LAST_LINEAR_16_SCALING);
+            FIRST_LOG2_BUCKET + log2(size - 1) - LOG2_128
         } else {
-            return BUCKET_LIST_SIZE - 1;
+            BUCKET_LIST_SIZE - 1
         }
     }
     /// Checks if ``space`` is in the bucket list.
@@ -225,14 +621,75 @@ impl BucketList {
         }
     }
 
+    /// Follows the free lists of all buckets and checks that every entry
+    /// is the space of a free block in the page with a size of the bucket.
+    /// Returns the first violation that is found.
+    pub fn verify_chains(&self) -> Result<(), MaraError> {
+        unsafe {
+            let max_chain_length = self.max_chain_length();
+            for bucket in 0..BUCKET_LIST_SIZE {
+                let mut space = self.get(bucket);
+                let mut chain_length = 0;
+                while let Some(current) = space {
+                    chain_length += 1;
+                    if chain_length > max_chain_length {
+                        return Err(MaraError::FreeListCycle { bucket });
+                    }
+                    self.verify_free_space(&current, bucket)?;
+                    space = current.read_next((*self.page).start_of_page());
+                }
+            }
+        }
+        Ok(())
+    }
+    /// Checks that ``space`` is the space of a free block in the page
+    /// that belongs to ``bucket``.
+    /// The bounds are checked before the code blocks are read.
+    unsafe fn verify_free_space(&self, space: &Space, bucket: usize) -> Result<(), MaraError> {
+        let start_of_page = (*self.page).start_of_page() as usize;
+        let end_of_page = (*self.page).end_of_page() as usize;
+        let ptr = space.ptr() as usize;
+        // leave room for the left code block and the next pointer
+        if ptr <= start_of_page || ptr + size_of::<NextPointerType>() > end_of_page {
+            return Err(MaraError::FreeListOutOfPage { bucket });
+        }
+        let (size, left_code_block) = code_block::read_from_right(space.ptr().sub(1));
+        if (left_code_block as usize) < start_of_page
+            || ptr + size > end_of_page
+            || !(*self.page).is_free(left_code_block)
+        {
+            return Err(MaraError::NotAFreeSpace { bucket });
+        }
+        // both code blocks of a block are equal
+        let right_code_block = space.ptr().add(size);
+        if code_block::read_from_left(right_code_block) != size {
+            return Err(MaraError::NotAFreeSpace { bucket });
+        }
+        #[cfg(not(feature = "free_map"))]
+        if !code_block::is_free(right_code_block) {
+            return Err(MaraError::NotAFreeSpace { bucket });
+        }
+        if Self::lookup_bucket(size) != bucket {
+            return Err(MaraError::WrongBucket { bucket });
+        }
+        Ok(())
+    }
+
     /////////////////////////////////
     // Checks
 
+    /// Check that the list holds a single space that spans the whole page.
+    /// Small pages store it in a lower bucket than the last one.
     pub fn check_init(&self) {
         #[cfg(feature = "consistency-checks")]
         {
-            // bucket list is empty
-            for i in 0..(BUCKET_LIST_SIZE - 1) {
+            // all other buckets are empty
+            let bucket = self
+                .bucket_list
+                .iter()
+                .position(|ptr| !ptr.is_null())
+                .expect("Bucket is empty");
+            for i in (bucket + 1)..BUCKET_LIST_SIZE {
                 if !(self.bucket_list[i].is_null()) {
                     dbg!(i);
                     dbg!(self.bucket_list[i]);
@@ -241,22 +698,57 @@ impl BucketList {
             }
             // The free space plus code blocks are as large as the page
             unsafe {
-                let space = self
-                    .get(BUCKET_LIST_SIZE - 1)
-                    .expect("Bucket is empty")
-                    .ptr() as *mut u8;
-                let (memory_size, block) = code_block::read_from_right(space.sub(1));
+                let space = self.get(bucket).expect("Bucket is empty");
+                if space.read_next((*self.page).start_of_page()).is_some() {
+                    panic!("more than one space in the bucket list")
+                }
+                let (memory_size, block) = code_block::read_from_right(space.ptr().sub(1));
                 let block_size = code_block::get_block_size(block, false);
-                if !(memory_size == (*self.page).page_size() - 2 * block_size) {
-                    dbg!(space);
+                if memory_size != (*self.page).page_size() - 2 * block_size {
+                    dbg!(space.ptr());
                     dbg!(block_size);
                     dbg!(memory_size);
                     dbg!((*self.page).page_size() - 2 * block_size);
                     panic!("space in bucket list does not equal page size")
                 }
+                if Self::lookup_bucket(memory_size) != bucket {
+                    dbg!(memory_size);
+                    dbg!(bucket);
+                    panic!("space of the page is in the wrong bucket")
+                }
             }
         }
     }
+    /// Check that no space is larger than ``largest_free_size``
+    pub fn check_largest_free(&self) {
+        #[cfg(feature = "consistency-checks")]
+        {
+            for bucket in 0..BUCKET_LIST_SIZE {
+                for space in self.iter_bucket(bucket) {
+                    if space.size_from_memory() > self.largest_free {
+                        dbg!(space.size_from_memory());
+                        dbg!(self.largest_free);
+                        panic!("free space is larger than the largest free size")
+                    }
+                }
+            }
+        }
+    }
+    /// Check that the bitmap marks exactly the non empty buckets
+    pub fn check_occupied(&self) {
+        #[cfg(feature = "consistency-checks")]
+        {
+            for i in 0..BUCKET_LIST_SIZE {
+                if self.bucket_list[i].is_null() == (self.occupied & (1 << i) != 0) {
+                    dbg!(i);
+                    dbg!(self.bucket_list[i]);
+                    dbg!(self.occupied);
+                    panic!("occupancy bitmap differs from the bucket list")
+                }
+            }
+        }
+    }
+    #[cfg_attr(not(feature = "consistency-checks"), allow(unused_variables))]
     pub fn check_found(&self, space: &Option<Space>, minimum_size: usize) {
         #[cfg(feature = "consistency-checks")]
         {
@@ -275,14 +767,15 @@ impl BucketList {
             }
         }
     }
+    #[cfg_attr(not(feature = "consistency-checks"), allow(unused_variables))]
     pub fn check_in_list(&self, space: &Space, expected: bool) {
         #[cfg(feature = "consistency-checks")]
         {
             unsafe {
-                if self.is_in_list(&space).0 != expected {
+                if self.is_in_list(space).0 != expected {
                     panic!(
                         "data is in list: {}\nexpected: {}",
-                        self.is_in_list(&space).0,
+                        self.is_in_list(space).0,
                         expected
                     )
                 }
@@ -290,3 +783,175 @@ impl BucketList {
         }
     }
 }
+
+#[test]
+fn test_verify_chains() {
+    use crate::Mara;
+    let mut memory = [0u8; 0x1000];
+    let mara = Mara::new(memory.as_mut_ptr(), memory.len());
+    let blocks: Vec<*mut u8> = (0..5).map(|_| mara.dynamic_new(16)).collect();
+    // the second and the fourth block are chained in the bucket for 16 bytes
+    mara.dynamic_delete(blocks[1]);
+    mara.dynamic_delete(blocks[3]);
+    let page = unsafe { &*mara.page_list().get_page() };
+    let bucket = BucketList::lookup_bucket(16);
+    assert_eq!(page.bucket_list().verify_chains(), Ok(()));
+
+    let head = blocks[3] as *mut NextPointerType;
+    let offset = |ptr: *mut u8| (ptr as usize - page.start_of_page() as usize) as NextPointerType;
+    let healthy = unsafe { head.read_unaligned() };
+    assert_eq!(healthy, offset(blocks[1]));
+    // user data that looks like a used single byte code block
+    unsafe { core::ptr::write_bytes(blocks[2], 0b1001_0000, 16) };
+    let interior = offset(unsafe { blocks[2].add(4) });
+    let outside = page.page_size() as NextPointerType;
+    let corruptions = [
+        (interior, MaraError::NotAFreeSpace { bucket }),
+        (offset(blocks[4]), MaraError::NotAFreeSpace { bucket }),
+        (offset(blocks[3]), MaraError::FreeListCycle { bucket }),
+        (outside, MaraError::FreeListOutOfPage { bucket }),
+    ];
+    for (next, error) in corruptions.iter() {
+        unsafe { head.write_unaligned(*next) };
+        assert_eq!(page.bucket_list().verify_chains(), Err(*error));
+    }
+    unsafe { head.write_unaligned(healthy) };
+    assert_eq!(page.bucket_list().verify_chains(), Ok(()));
+}
+
+#[test]
+fn test_iter_bucket_of_a_malformed_list() {
+    use crate::Mara;
+    let mut memory = [0u8; 0x1000];
+    let mara = Mara::new(memory.as_mut_ptr(), memory.len());
+    let blocks: Vec<*mut u8> = (0..5).map(|_| mara.dynamic_new(16)).collect();
+    mara.dynamic_delete(blocks[1]);
+    mara.dynamic_delete(blocks[3]);
+    let page = unsafe { &*mara.page_list().get_page() };
+    let bucket_list = page.bucket_list();
+    let bucket = BucketList::lookup_bucket(16);
+    let chain: Vec<*mut u8> = bucket_list
+        .iter_bucket(bucket)
+        .map(|space| space.ptr())
+        .collect();
+    assert_eq!(chain, [blocks[3], blocks[1]]);
+    assert!(bucket_list
+        .iter_bucket(bucket)
+        .all(|space| unsafe { page.is_free(space.ptr().sub(1)) }));
+
+    let head = blocks[3] as *mut NextPointerType;
+    let offset = |ptr: *mut u8| (ptr as usize - page.start_of_page() as usize) as NextPointerType;
+    let healthy = unsafe { head.read_unaligned() };
+    // a cycle is cut after as many spaces as fit in the page
+    unsafe { head.write_unaligned(offset(blocks[3])) };
+    assert_eq!(
+        bucket_list.iter_bucket(bucket).count(),
+        bucket_list.max_chain_length()
+    );
+    // a space outside of the page ends the iteration
+    unsafe { head.write_unaligned(page.page_size() as NextPointerType) };
+    assert_eq!(bucket_list.iter_bucket(bucket).count(), 1);
+    unsafe { head.write_unaligned(healthy) };
+    assert_eq!(bucket_list.iter_bucket(bucket).count(), 2);
+}
+
+#[test]
+fn test_lookup_bucket_constants() {
+    // the recursive definition the constants are derived from
+    fn reference(size: usize) -> usize {
+        if size <= LAST_LINEAR_4_SCALING {
+            (size - 1) / 4
+        } else if size <= LAST_LINEAR_16_SCALING {
+            reference(LAST_LINEAR_4_SCALING) + 1 + (size - LAST_LINEAR_4_SCALING - 1) / 16
+        } else if size <= LARGEST_BUCKET_SIZE {
+            reference(LAST_LINEAR_16_SCALING) + 1 + log2(size - 1) - log2(LAST_LINEAR_16_SCALING)
+        } else {
+            BUCKET_LIST_SIZE - 1
+        }
+    }
+    for size in (1..4 * LARGEST_BUCKET_SIZE).chain(vec![1 << 20, 1 << 31, usize::MAX]) {
+        assert_eq!(BucketList::lookup_bucket(size), reference(size), "size {}", size);
+    }
+    assert_eq!(BucketList::lookup_bucket(LARGEST_BUCKET_SIZE), BUCKET_LIST_SIZE - 2);
+}
+
+#[test]
+fn test_bucket_max_size() {
+    for index in 0..BUCKET_LIST_SIZE - 1 {
+        let max = BucketList::bucket_max_size(index);
+        assert_eq!(BucketList::lookup_bucket(max), index);
+        assert_eq!(BucketList::lookup_bucket(max + 1), index + 1);
+    }
+    assert_eq!(BucketList::bucket_max_size(BUCKET_LIST_SIZE - 1), usize::MAX);
+}
+
+#[test]
+fn test_occupied_bitmap() {
+    use crate::Mara;
+    let mut memory = [0u8; 0x1000];
+    let mara = Mara::new(memory.as_mut_ptr(), memory.len());
+    let page = unsafe { &*mara.page_list().get_page() };
+    let last = 1 << (BUCKET_LIST_SIZE - 1);
+    assert_eq!(page.bucket_list().occupied, last);
+    let sizes = [8, 16, 100, 500];
+    let blocks: Vec<*mut u8> = sizes
+        .iter()
+        .flat_map(|&size| vec![mara.dynamic_new(size), mara.dynamic_new(8)])
+        .collect();
+    // free the blocks in front of the separators
+    for &ptr in blocks.iter().step_by(2) {
+        mara.dynamic_delete(ptr);
+    }
+    let expected = sizes.iter().fold(last, |bits, &size| {
+        bits | 1 << BucketList::lookup_bucket(size)
+    });
+    assert_eq!(page.bucket_list().occupied, expected);
+    // the empty buckets between 16 and 100 bytes are skipped
+    let start = BucketList::lookup_bucket(20);
+    let mut budget = usize::MAX;
+    let bucket = page.bucket_list().find_non_empty_bucket(start, &mut budget);
+    assert_eq!(bucket, BucketList::lookup_bucket(100));
+    assert_eq!(usize::MAX - budget, bucket - start);
+    // reusing the holes empties their buckets
+    for &size in &sizes {
+        assert!(!mara.dynamic_new(size).is_null());
+    }
+    assert_eq!(page.bucket_list().occupied, last);
+}
+
+#[test]
+fn test_no_fitting_space_in_any_bucket() {
+    use crate::page_list::PageList;
+    let mut memory = [0u8; 0x1000];
+    let mut page_list = PageList::new(memory.as_mut_ptr(), memory.len());
+    let page = page_list.get_page();
+    let free_size = unsafe { (*page).max_payload_size() };
+    let get_free_space = |size: usize| {
+        let mut budget = usize::MAX;
+        unsafe { (*page).bucket_list().get_free_space(size, &mut budget) }
+    };
+    // the only space is in the last bucket
+    assert!(get_free_space(free_size + 1).is_none());
+    assert!(get_free_space(free_size).is_some());
+    // the only space is in a bucket below the last one
+    let mut alloc_data = crate::AllocationData::new();
+    alloc_data.space.set_size(free_size - 100);
+    let mut budget = usize::MAX;
+    page_list.dynamic_new(&mut alloc_data, &mut budget);
+    let rest_size = page_list.free_blocks().next().unwrap().space.size();
+    assert!(BucketList::lookup_bucket(rest_size) < BUCKET_LIST_SIZE - 1);
+    assert!(get_free_space(rest_size + 1).is_none());
+    assert!(get_free_space(rest_size).is_some());
+}
+
+#[test]
+fn test_check_init_of_a_small_page() {
+    use crate::page_list::PageList;
+    // the initial space of the page is too small for the last bucket
+    let mut memory = [0u8; 0x400];
+    let page_list = PageList::new(memory.as_mut_ptr(), memory.len());
+    let page = unsafe { &*page_list.get_page() };
+    assert!(BucketList::lookup_bucket(page.max_payload_size()) < BUCKET_LIST_SIZE - 1);
+    page.bucket_list().check_init();
+    assert_eq!(page_list.verify(), Ok(()));
+}
@@ -1,9 +1,7 @@
 #![cfg(feature = "consistency_tests")]
 
-use crate::code_block;
-use crate::page::Page;
 use crate::space::Space;
-use crate::{AllocationData, Mara};
+use crate::{InsertionPolicy, Mara};
 use core::mem::size_of;
 use rand::distributions::{
     uniform::{UniformFloat, UniformSampler},
@@ -24,6 +22,29 @@ pub enum FillRequestedMemory {
     AddressShortcut,
 }
 
+/// The results of a test run
+#[derive(PartialEq, Debug, Eq, Copy, Clone, Default)]
+pub struct TestReport {
+    /// the seed that was used for the rng
+    pub seed: usize,
+    /// duration of the run
+    pub seconds: u64,
+    /// the maximum of simultaneously allocated dynamic bytes
+    pub dynamic_memory_peak: usize,
+    /// the maximum of simultaneously allocated dynamic blocks
+    pub dynamic_blocks_peak: usize,
+    /// the maximum of allocated static bytes.
+    /// Stays zero as long as the test does not allocate static blocks
+    pub static_memory_peak: usize,
+    /// the maximum of allocated static blocks.
+    /// Stays zero as long as the test does not allocate static blocks
+    pub static_blocks_peak: usize,
+    /// Number of blocks, which do not contain the original content.
+    pub corrupted_blocks: usize,
+    /// Number of FreeSpaces which can not be found in the BucketList ( => memory leaks)
+    pub free_space_not_in_bucket_list: usize,
+}
+
 pub struct Test {
     /// Number of FreeSpaces which can not be found in the BucketList ( => memory leaks)
     free_space_not_in_bucket_list: usize,
@@ -65,6 +86,8 @@ pub struct TestBuilder {
     max_iterations: usize,
     /// the seed that is used for the rng
     seed: usize,
+    /// where freed blocks are added to their bucket
+    insertion_policy: InsertionPolicy,
 
     memory: *mut u8,
     memory_size: usize,
@@ -81,6 +104,7 @@ impl TestBuilder {
             average_size: 16,
             max_size: 1000,
             seed: 123456789,
+            insertion_policy: InsertionPolicy::Lifo,
             memory,
             memory_size,
         }
@@ -88,6 +112,7 @@ impl TestBuilder {
 
     pub fn build(self) -> Test {
         let mara = Mara::new(self.memory, self.memory_size);
+        mara.set_insertion_policy(self.insertion_policy);
         Test {
             free_space_not_in_bucket_list: 0,
             corrupted_blocks: 0,
@@ -135,9 +160,17 @@ impl TestBuilder {
         self.seed = seed;
         self
     }
+    pub fn insertion_policy(mut self, policy: InsertionPolicy) -> Self {
+        self.insertion_policy = policy;
+        self
+    }
 }
 
 impl Test {
+    /// The allocator under test, e.g. to inspect the heap after a run
+    pub fn mara(&self) -> &Mara {
+        &self.mara
+    }
     /// Runs a test with the arguments given. Customizable parameters are amount of requests, probability to request
     /// dynamic memory, probability to free a dynamic block after each (dynamic AND static) request, size information
     /// about the requested blocks (min, max), iterations (after each iteration, all
@@ -147,10 +180,11 @@ impl Test {
     /// The time for each run is measured. After a run, a consistency check is performed.
     /// After completion, prints information about the test in the following order:
     /// type seed time dynamicMemoryPeak dynamicBlocksPeak staticMemoryPeak staticBlockPeak corrupted_blocks freeSpaceNotInBL
-    pub fn run(&mut self) {
+    /// The same information is returned in the report.
+    pub fn run(&mut self) -> TestReport {
         let begin;
         let mut dynamic_pointers: Vec<Space>;
-        if cfg!(no_std) {
+        if cfg!(feature = "no_std") {
             unimplemented!()
         } else {
             use std::time::Instant;
@@ -163,7 +197,13 @@ impl Test {
         let probability_distribution: UniformFloat<f64> = UniformFloat::new(0.0, 1.0);
         let size_distribution = Uniform::new(self.min_size, self.max_size);
 
-        println!("seed\tseconds");
+        let mut report = TestReport {
+            seed: self.seed,
+            ..TestReport::default()
+        };
+        let mut dynamic_memory = 0;
+
+        println!("seed\tseconds\tdynamicMemoryPeak\tdynamicBlocksPeak\tstaticMemoryPeak\tstaticBlockPeak\tcorrupted_blocks\tfreeSpaceNotInBL");
         for _iterations in 0..=self.max_iterations {
             for _v in 0..=self.amount_new_variables {
                 let mut var_size;
@@ -181,6 +221,9 @@ impl Test {
                 space.set_ptr(self.mara.dynamic_new(var_size));
                 space.set_size(var_size);
                 dynamic_pointers.push(space);
+                dynamic_memory += var_size;
+                report.dynamic_memory_peak = report.dynamic_memory_peak.max(dynamic_memory);
+                report.dynamic_blocks_peak = report.dynamic_blocks_peak.max(dynamic_pointers.len());
                 if self.fill_strategy != FillRequestedMemory::NoFill {
                     // write address to address
                     self.write_space(&mut space);
@@ -190,7 +233,7 @@ impl Test {
                 let rnd_val: f64 = probability_distribution.sample(&mut rng);
                 if !dynamic_pointers.is_empty() && rnd_val <= self.p_free {
                     let deleted_index =
-                        rng.sample(dynamic_variable_distribution) % dynamic_pointers.len() as usize;
+                        rng.sample(dynamic_variable_distribution) % dynamic_pointers.len();
                     let to_delete = *dynamic_pointers.get(deleted_index).expect("item not found");
                     unsafe {
                         for i in 0..to_delete.size() {
@@ -200,13 +243,28 @@ impl Test {
 
                     self.mara.dynamic_delete(to_delete.ptr());
                     dynamic_pointers.remove(deleted_index);
+                    dynamic_memory -= to_delete.size();
                     // self.check_page();
                 }
             }
             self.check_page();
             let elapsed = begin.elapsed();
-            println!("{}\t{}", self.seed, elapsed.as_secs(),);
+            report.seconds = elapsed.as_secs();
+            report.corrupted_blocks = self.corrupted_blocks;
+            report.free_space_not_in_bucket_list = self.free_space_not_in_bucket_list;
+            println!(
+                "{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}",
+                report.seed,
+                report.seconds,
+                report.dynamic_memory_peak,
+                report.dynamic_blocks_peak,
+                report.static_memory_peak,
+                report.static_blocks_peak,
+                report.corrupted_blocks,
+                report.free_space_not_in_bucket_list,
+            );
         }
+        report
     }
 
     /**
@@ -217,7 +275,6 @@ impl Test {
      * \param address the block's starting address
      * \param size the block's size
      */
-
     fn write_space(&self, space: &mut Space) {
         if self.fill_strategy != FillRequestedMemory::NoFill {
             let value_at_address = match self.fill_strategy {
@@ -244,13 +301,11 @@ impl Test {
             self.corrupted_blocks = 0;
 
             let page = self.mara.page_list().get_page();
-            let mut alloc = AllocationData::new();
-            alloc.set_page(page as *mut Page);
-            alloc.set_data_start((*page).start_of_page() as *mut u8);
-            alloc.cache_code_blocks();
-            loop {
+            let mut last_end = None;
+            for alloc in (*page).blocks() {
                 alloc.check_consistency();
-                if !code_block::is_free(alloc.data_start()) {
+                last_end = Some(alloc.data_end());
+                if !alloc.is_free() {
                     (*page).bucket_list().check_in_list(&alloc.space, false);
                     if self.fill_strategy != FillRequestedMemory::NoFill {
                         for i in 0..(alloc.space.size() / size_of::<usize>()) {
@@ -275,13 +330,8 @@ impl Test {
                 } else {
                     (*page).bucket_list().check_in_list(&alloc.space, true);
                 }
-                if let Some(neighbor) = alloc.right_neighbor() {
-                    alloc = neighbor;
-                } else {
-                    assert_eq!(alloc.data_end(), (*page).end_of_page() as *mut u8);
-                    break;
-                }
             }
+            assert_eq!(last_end, Some((*page).end_of_page() as *mut u8));
         }
     }
 }
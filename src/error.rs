@@ -0,0 +1,135 @@
+use core::fmt;
+
+/// Errors that Mara reports instead of corrupting its memory
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum MaraError {
+    /// The offset of a free space from the start of its page does not fit
+    /// in a ``NextPointerType``
+    NextPointerOutOfRange,
+    /// No free block is large enough for the request
+    OutOfMemory {
+        /// size of the largest free block, a request of this size would succeed
+        largest_available: usize,
+    },
+    /// The request is larger than a block spanning a whole page and can
+    /// never be satisfied
+    ExceedsMaxAllocation {
+        /// size of the largest block an empty heap can hand out
+        max_allocation: usize,
+    },
+    /// A free list entry of the bucket lies outside of its page
+    FreeListOutOfPage { bucket: usize },
+    /// The free list of the bucket contains a cycle
+    FreeListCycle { bucket: usize },
+    /// A free list entry of the bucket is not the space of a free block
+    NotAFreeSpace { bucket: usize },
+    /// A free space is stored in a bucket that does not match its size
+    WrongBucket { bucket: usize },
+    /// The pages do not form a single ring that contains the first page
+    BrokenPageRing,
+    /// A pointer that is not the start of a block in any page was freed
+    InvalidFree,
+    /// The freed block is already free
+    DoubleFree,
+}
+
+/// Errors of the fallible code block decoders for bytes that might be corrupted
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum CodeBlockError {
+    /// The decoded size is smaller than a next pointer or larger than the
+    /// space of a block spanning the largest page
+    SizeOutOfRange { size: usize },
+    /// The continuation bits do not end the code block within the length of
+    /// the code block of the largest page
+    MalformedContinuation,
+    /// The code block reaches in front of the memory that may be read
+    OutOfBounds,
+    /// The right code block encodes another size than the left one
+    SizeMismatch { left: usize, right: usize },
+    /// The right code block is longer or shorter than the left one
+    LengthMismatch,
+    /// Only one of the code blocks marks the block as free
+    FreeBitMismatch,
+}
+
+impl fmt::Display for CodeBlockError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CodeBlockError::SizeOutOfRange { size } => {
+                write!(
+                    f,
+                    "code block encodes {} bytes, no space has this size",
+                    size
+                )
+            }
+            CodeBlockError::MalformedContinuation => {
+                write!(f, "code block is longer than any valid code block")
+            }
+            CodeBlockError::OutOfBounds => {
+                write!(f, "code block reaches out of the readable memory")
+            }
+            CodeBlockError::SizeMismatch { left, right } => write!(
+                f,
+                "left code block encodes {} bytes, the right one {} bytes",
+                left, right
+            ),
+            CodeBlockError::LengthMismatch => {
+                write!(f, "the code blocks of the block differ in length")
+            }
+            CodeBlockError::FreeBitMismatch => {
+                write!(f, "only one code block of the block is marked as free")
+            }
+        }
+    }
+}
+
+impl fmt::Display for MaraError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MaraError::NextPointerOutOfRange => {
+                write!(
+                    f,
+                    "next pointer offset does not fit in the next pointer type"
+                )
+            }
+            MaraError::OutOfMemory { largest_available } => write!(
+                f,
+                "out of memory, the largest free block has {} bytes",
+                largest_available
+            ),
+            MaraError::ExceedsMaxAllocation { max_allocation } => write!(
+                f,
+                "request exceeds the maximum single allocation of {} bytes",
+                max_allocation
+            ),
+            MaraError::FreeListOutOfPage { bucket } => {
+                write!(f, "free list of bucket {} leaves the page", bucket)
+            }
+            MaraError::FreeListCycle { bucket } => {
+                write!(f, "free list of bucket {} contains a cycle", bucket)
+            }
+            MaraError::NotAFreeSpace { bucket } => {
+                write!(
+                    f,
+                    "free list of bucket {} points to a non free space",
+                    bucket
+                )
+            }
+            MaraError::WrongBucket { bucket } => {
+                write!(
+                    f,
+                    "free list of bucket {} contains a space of another size",
+                    bucket
+                )
+            }
+            MaraError::BrokenPageRing => {
+                write!(f, "the pages do not form a ring with the first page")
+            }
+            MaraError::InvalidFree => write!(
+                f,
+                "the freed pointer is not in any page or not the start of a block"
+            ),
+            MaraError::DoubleFree => write!(f, "the freed block is already free"),
+        }
+    }
+}
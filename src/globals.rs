@@ -11,13 +11,65 @@ pub const BUCKET_LIST_SIZE: usize = LAST_LINEAR_4_SCALING / 4
     + 1;
 
 // The next pointer is an offset from the page start so we can go at most ~4GB with an u32
-pub const MAX_PAGE_SIZE: usize = NextPointerType::max_value() as usize + 1; //2^32 byte ~ 4Gb
+// (64 KiB with ``bit16``)
+#[cfg(not(feature = "bit64"))]
+pub const MAX_PAGE_SIZE: usize = NextPointerType::MAX as usize + 1; //2^32 byte ~ 4Gb
+// 64 bit offsets are not the limit anymore, the page size is kept in a sane range instead
+#[cfg(feature = "bit64")]
+pub const MAX_PAGE_SIZE: usize = 1 << 44; //2^44 byte ~ 16Tb
 
-// a next pointer plus tow code blocks
-pub const SMALLEST_POSSIBLE_FREE_SPACE: usize = size_of::<NextPointerType>() + 2;
+// every page records at most this many pinned blocks and excluded ranges
+pub const MAX_PINNED_BLOCKS: usize = 8;
 
+// ``Mara::new_auto`` splits a region in about this many pages
+pub const AUTO_PAGE_COUNT: usize = 16;
+// ``Mara::new_auto`` creates no smaller pages, a smaller region becomes a single page
+#[cfg(not(feature = "bit16"))]
+pub const AUTO_MIN_PAGE_SIZE: usize = 0x1_0000; // 64 KiB
+// ``Mara::new_auto`` creates no larger pages, well below the limit of the next pointer offsets
+#[cfg(not(feature = "bit16"))]
+pub const AUTO_MAX_PAGE_SIZE: usize = 0x8000_0000; // 2 GiB
+// 16 bit offsets leave no room for a range, every page gets half the limit
+#[cfg(feature = "bit16")]
+pub const AUTO_MIN_PAGE_SIZE: usize = 0x8000; // 32 KiB
+#[cfg(feature = "bit16")]
+pub const AUTO_MAX_PAGE_SIZE: usize = 0x8000; // 32 KiB
+
+// every allocated space has to hold a next pointer once it is freed
+pub const SMALLEST_POSSIBLE_SPACE: usize = size_of::<NextPointerType>();
+
+// a next pointer plus two code blocks, spaces this small are encoded in one byte each
+pub const SMALLEST_POSSIBLE_FREE_SPACE: usize = SMALLEST_POSSIBLE_SPACE + 2;
+
+// the largest alignment aligned allocations accept, one memory page
+pub const MAX_ALIGN: usize = 0x1000;
+
+// returned for zero sized requests, aligned to every accepted alignment.
+// No segment may contain this address, so no block can start there
+pub const ZERO_SIZE_BLOCK: usize = MAX_ALIGN;
+
+#[cfg(all(feature = "bit16", feature = "bit64"))]
+compile_error!("Mara: the features bit16 and bit64 exclude each other");
+
+#[cfg(all(feature = "free_bit_low", feature = "free_map"))]
+compile_error!("Mara: the features free_bit_low and free_map exclude each other");
+
+// no bit8, a page struct alone is larger than 256 bytes
+#[cfg(feature = "bit16")]
+pub type NextPointerType = u16;
+#[cfg(not(any(feature = "bit16", feature = "bit64")))]
 pub type NextPointerType = u32;
-pub const ERROR_NEXT_POINTER: NextPointerType = NextPointerType::max_value(); // just ones
+#[cfg(feature = "bit64")]
+pub type NextPointerType = u64;
+pub const ERROR_NEXT_POINTER: NextPointerType = NextPointerType::MAX; // just ones
+
+// stored in every page to detect writes into the page struct
+#[cfg(feature = "canary")]
+pub const PAGE_CANARY: usize = 0x4D61_7261; // "Mara"
+
+// fills the free spaces behind their next pointer, see ``Mara::with_poison_byte``
+#[cfg(feature = "poison")]
+pub const POISON_BYTE: u8 = 0xDE;
 
 fn log2_64(x: u64) -> usize {
     if x == 0 {
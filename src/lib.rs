@@ -1,87 +1,910 @@
+// the Allocator trait is still unstable
+#![cfg_attr(feature = "allocator_api", feature(allocator_api))]
 #[cfg_attr(feature = "no_std", no_std)]
 extern crate alloc;
 
 mod allocation_data;
+#[cfg(feature = "allocator_api")]
+mod allocator;
+mod block_info;
 mod bucket_list;
 mod code_block;
 mod consistency;
+mod error;
 mod globals;
+mod lock;
 mod page;
 mod page_list;
+#[cfg(not(feature = "no_std"))]
+mod snapshot;
 mod space;
+#[cfg(feature = "statistic")]
+mod statistic;
+mod strategy;
 
+pub use allocation_data::AllocationData;
+pub use block_info::BlockInfo;
+pub use bucket_list::{FitPolicy, InsertionPolicy};
+pub use code_block::validate as validate_code_blocks;
 #[cfg(feature = "consistency_tests")]
-pub use consistency::TestBuilder;
+pub use consistency::{TestBuilder, TestReport};
+pub use error::{CodeBlockError, MaraError};
+pub use globals::{MAX_ALIGN, SMALLEST_POSSIBLE_FREE_SPACE, SMALLEST_POSSIBLE_SPACE};
+pub use page_list::PagePolicy;
+#[cfg(not(feature = "no_std"))]
+pub use snapshot::{HeapDiff, HeapSnapshot};
+#[cfg(feature = "statistic")]
+pub use statistic::{SearchStats, Statistics, TimingBreakdown};
+pub use strategy::AllocStrategy;
 
 use alloc::alloc::{GlobalAlloc, Layout};
-use allocation_data::AllocationData;
 use core::cell::UnsafeCell;
-use core::mem::transmute;
+use core::ptr::NonNull;
+use lock::{Lock, Locked};
 use page::Page;
 use page_list::PageList;
 
+/// With the ``sync`` feature every call that changes the heap takes a spin
+/// lock, so Mara can be shared between threads, e.g. as global allocator.
+/// The inspection and statistic methods lock as well, the iterators hold
+/// the lock until they are dropped.
 pub struct Mara {
     page_list: UnsafeCell<PageList>,
+    lock: Lock,
 }
 
+#[cfg(feature = "sync")]
+unsafe impl Sync for Mara {}
+
 impl Mara {
+    /// Manages the data array in pages of ``auto_page_size`` bytes, so that
+    /// every free list of a large region covers only a part of it.
+    /// Use ``with_page_size`` for another page size.
     /// #### data
     /// start of data array
     /// #### data_size
     /// length of the data array in bytes
     pub fn new(data: *mut u8, data_size: usize) -> Self {
+        Self::with_page_size(data, data_size, Self::auto_page_size(data_size))
+    }
+
+    /// The same as ``new``, the page size is picked by ``auto_page_size``
+    pub fn new_auto(data: *mut u8, data_size: usize) -> Self {
+        Self::new(data, data_size)
+    }
+
+    /// The page size ``new_auto`` picks for a region of ``data_size`` bytes.
+    /// The region is split into ``AUTO_PAGE_COUNT`` (16) pages, but no page
+    /// is smaller than ``AUTO_MIN_PAGE_SIZE`` (64 KiB) or larger than
+    /// ``AUTO_MAX_PAGE_SIZE`` (2 GiB).
+    /// Regions up to 64 KiB stay a single page.
+    pub fn auto_page_size(data_size: usize) -> usize {
+        (data_size / globals::AUTO_PAGE_COUNT)
+            .clamp(globals::AUTO_MIN_PAGE_SIZE, globals::AUTO_MAX_PAGE_SIZE)
+            .min(data_size)
+    }
+
+    /// Splits the region into pages of at most ``page_size`` bytes.
+    /// The pages have the same size, up to one byte, so the last page is
+    /// not a small rest. A block never spans two pages, so the page size
+    /// bounds the largest allocation.
+    /// #### page_size
+    /// at most ``data_size`` and ``MAX_PAGE_SIZE``,
+    /// every page needs at least ``min_page_size`` bytes
+    pub fn with_page_size(data: *mut u8, data_size: usize, page_size: usize) -> Self {
+        if page_size > globals::MAX_PAGE_SIZE {
+            panic!("Mara: Max page size is {} bytes", globals::MAX_PAGE_SIZE);
+        }
+        if page_size == 0 || page_size > data_size {
+            panic!(
+                "Mara: page size {} does not fit in {} bytes of data",
+                page_size, data_size
+            );
+        }
+        let page_list = UnsafeCell::new(PageList::with_page_size(data, data_size, page_size));
+        Self {
+            page_list,
+            lock: Lock::new(),
+        }
+    }
+
+    /// The smallest page or segment Mara can manage. Each page starts with
+    /// its bookkeeping, which has to be followed by at least one free block.
+    pub fn min_page_size() -> usize {
+        page_list::min_page_size(0)
+    }
+
+    /// The number of pages, one for every segment or as picked by ``new_auto``
+    pub fn page_count(&self) -> usize {
+        let _lock = self.lock.lock();
+        self.page_list().page_count()
+    }
+
+    /// Manages several disjoint memory regions, e.g. internal and external RAM.
+    /// Every segment becomes an own page. Allocations are taken from the first
+    /// segment that has a fitting free block, in the given order.
+    /// A block never spans two segments, even if they are adjacent in memory.
+    /// #### segments
+    /// start and length in bytes of each region
+    pub fn from_segments(segments: &[(*mut u8, usize)]) -> Self {
+        for &(_, data_size) in segments {
+            if data_size > globals::MAX_PAGE_SIZE {
+                panic!("Mara: Max page size is {} bytes", globals::MAX_PAGE_SIZE);
+            }
+        }
+        let page_list = UnsafeCell::new(PageList::from_segments(segments, 0));
+        Self {
+            page_list,
+            lock: Lock::new(),
+        }
+    }
+
+    /// Like ``new`` but reserves ``user_data_size`` bytes in front of the
+    /// managed memory for own metadata, e.g. an arena id. The bytes are never
+    /// touched by Mara and accessed with ``user_data``.
+    pub fn with_user_data(data: *mut u8, data_size: usize, user_data_size: usize) -> Self {
         if data_size > globals::MAX_PAGE_SIZE {
             panic!("Mara: Max page size is {} bytes", globals::MAX_PAGE_SIZE);
         }
-        let page_list = UnsafeCell::new(PageList::new(data, data_size));
-        Self { page_list }
+        let page_list =
+            UnsafeCell::new(PageList::from_segments(&[(data, data_size)], user_data_size));
+        Self {
+            page_list,
+            lock: Lock::new(),
+        }
+    }
+
+    /// Like ``new`` but freed blocks with less than ``coalesce_threshold``
+    /// bytes are not merged with their free neighbors. They stay in the free
+    /// list of their size and are reused quickly by allocations of the same
+    /// size, while larger blocks are merged to keep fragmentation low.
+    pub fn with_coalesce_threshold(
+        data: *mut u8,
+        data_size: usize,
+        coalesce_threshold: usize,
+    ) -> Self {
+        let mara = Self::new(data, data_size);
+        mara.page_list().set_coalesce_threshold(coalesce_threshold);
+        mara
+    }
+
+    /// Like ``new`` but the free spaces are filled with ``poison_byte``
+    /// instead of ``POISON_BYTE`` (0xDE), e.g. a value that does not occur in
+    /// the own data and is easy to spot in a memory dump
+    #[cfg(feature = "poison")]
+    pub fn with_poison_byte(data: *mut u8, data_size: usize, poison_byte: u8) -> Self {
+        let mara = Self::new(data, data_size);
+        mara.page_list().set_poison_byte(poison_byte);
+        mara
     }
 
+    /// The bytes reserved with ``with_user_data`` in the page with the given
+    /// index, pages are counted in the order of the segments.
+    /// Empty if nothing was reserved.
+    /// Mara is borrowed mutably, so there is only one slice at a time.
+    pub fn user_data(&mut self, page_index: usize) -> &mut [u8] {
+        let page_list = self.page_list.get_mut();
+        page_list.check_initialized();
+        unsafe { (*page_list.page(page_index)).user_data() }
+    }
+
+    #[allow(clippy::mut_from_ref)]
     pub(crate) fn page_list(&self) -> &mut PageList {
-        unsafe { transmute::<*mut PageList, &mut PageList>(self.page_list.get()) }
+        let page_list = unsafe { &mut *self.page_list.get() };
+        page_list.check_initialized();
+        page_list
     }
     /// Reserves memory in the static sector. Memory in this sector is expected to live as long as Mara. Memory
     /// allocated with this function CANNOT be freed. Mara returns a pointer to the location with an unused block with the
     /// given size and completely ignore this space in the future. The advantage is that these blocks will produce absolutely
-    /// no additional.
+    /// no additional overhead.
+    /// The static sector grows down from the end of each page, so the free block at the end of a page has
+    /// to be large enough.
     /// #### size_in_byte
     /// size of the block you want to use
     /// #### return
-    /// a pointer to the first byte of the block you want to use. After this operation the block will stay allocated
-    /// until complete program termination.
-    pub fn static_new(&self, _size_in_byte: usize) -> *mut u8 {
-        unimplemented!();
-        //self.page_list().static_new(size_in_byte)
+    /// a pointer to the first byte of the block you want to use or null if no page has room at its end.
+    /// After this operation the block will stay allocated until complete program termination.
+    pub fn static_new(&self, size_in_byte: usize) -> *mut u8 {
+        let _lock = self.lock.lock();
+        self.page_list()
+            .static_new(size_in_byte)
+            .unwrap_or(core::ptr::null_mut())
     }
 
     /**
      * Reserves memory in the dynamic sector. Memory in this sector can be freed using the dynamicDelete method.
+     * Blocks have at least the size of a next pointer (4 byte, 2 byte with ``bit16`` and 8 byte with ``bit64``), smaller requests are rounded up.
+     * A request of 0 bytes does not touch the heap, it returns the same non null pointer every time, which is ignored by ``dynamic_delete``.
      * @param size_in_byte how many bytes shall be reserved
      * @return a pointer to the first byte in a reserved space with at least the requested size
+     * or null if no free space is large enough, see ``try_dynamic_new`` for the reason
      */
     pub fn dynamic_new(&self, size_in_byte: usize) -> *mut u8 {
+        self.try_dynamic_new(size_in_byte).unwrap_or(core::ptr::null_mut())
+    }
+
+    /// Like ``dynamic_new`` but the whole space of the block is zeroed.
+    /// The space might be larger than requested, the code block behind it is
+    /// left untouched.
+    /// #### size_in_byte
+    /// how many bytes shall be reserved
+    /// #### return
+    /// a pointer to the first byte of the zeroed space or null if no free space
+    /// is large enough
+    pub fn dynamic_new_zeroed(&self, size_in_byte: usize) -> *mut u8 {
+        if size_in_byte == 0 {
+            return globals::ZERO_SIZE_BLOCK as *mut u8;
+        }
+        let _lock = self.lock.lock();
         let mut allocation_data = AllocationData::new();
         allocation_data.space.set_size(size_in_byte);
-        self.page_list().dynamic_new(&mut allocation_data);
-        allocation_data.space.ptr()
+        let mut budget = usize::MAX;
+        self.page_list().dynamic_new(&mut allocation_data, &mut budget);
+        if !allocation_data.space.is_some() {
+            return core::ptr::null_mut();
+        }
+        let ptr = allocation_data.space.ptr();
+        // the size includes a remainder that was too small to split off
+        let size = allocation_data.space.size();
+        unsafe { core::ptr::write_bytes(ptr, 0, size) };
+        ptr
+    }
+
+    /// Resizes the block at ``ptr`` so that it can hold ``new_size`` bytes.
+    /// The block is grown in place if its right neighbor is free and large
+    /// enough, otherwise a new block is reserved, the content is copied and
+    /// the old block is freed.
+    /// A shrunk block stays in place and its tail is freed if it is large
+    /// enough to be managed as free space.
+    /// #### ptr
+    /// a pointer that was returned by ``dynamic_new``
+    /// #### return
+    /// a pointer to the resized block or null if no space was found,
+    /// the old block stays valid in that case
+    pub fn dynamic_resize(&self, ptr: *mut u8, new_size: usize) -> *mut u8 {
+        if ptr as usize == globals::ZERO_SIZE_BLOCK {
+            return self.dynamic_new(new_size);
+        }
+        let _lock = self.lock.lock();
+        self.page_list()
+            .resize(ptr, new_size, 1)
+            .unwrap_or(core::ptr::null_mut())
+    }
+
+    /// Like ``dynamic_new`` but with a bounded worst case latency.
+    /// Every scanned bucket, every visited free space and the split of the
+    /// found space cost one step.
+    /// #### size_in_byte
+    /// how many bytes shall be reserved
+    /// #### max_steps
+    /// the amount of work the allocation may do
+    /// #### return
+    /// a pointer to the first byte in a reserved space with at least the requested size
+    /// or null if no space was found within ``max_steps``.
+    /// A request of 0 bytes returns the pointer of ``dynamic_new(0)``.
+    pub fn dynamic_new_bounded(&self, size_in_byte: usize, max_steps: usize) -> *mut u8 {
+        if size_in_byte == 0 {
+            return globals::ZERO_SIZE_BLOCK as *mut u8;
+        }
+        let _lock = self.lock.lock();
+        let mut allocation_data = AllocationData::new();
+        allocation_data.space.set_size(size_in_byte);
+        let mut budget = max_steps;
+        self.page_list().dynamic_new(&mut allocation_data, &mut budget);
+        if allocation_data.space.is_some() {
+            allocation_data.space.ptr()
+        } else {
+            core::ptr::null_mut()
+        }
+    }
+
+    /// Allocates a block of ``size_in_byte`` bytes for every entry of ``blocks``,
+    /// e.g. to warm a pool. Stops early if the heap is full.
+    /// #### return
+    /// the number of blocks that were allocated, only these entries are set
+    pub fn dynamic_new_batch(&self, size_in_byte: usize, blocks: &mut [*mut u8]) -> usize {
+        self.dynamic_new_batch_with_progress(size_in_byte, blocks, blocks.len().max(1), |_| true)
+    }
+
+    /// Like ``dynamic_new_batch`` but reports the progress of long batches.
+    /// #### interval
+    /// number of allocations between two calls of ``progress``
+    /// #### progress
+    /// called with the number of blocks allocated so far,
+    /// the batch stops if it returns false
+    /// #### return
+    /// the number of blocks that were allocated, only these entries are set
+    pub fn dynamic_new_batch_with_progress(
+        &self,
+        size_in_byte: usize,
+        blocks: &mut [*mut u8],
+        interval: usize,
+        mut progress: impl FnMut(usize) -> bool,
+    ) -> usize {
+        if interval == 0 {
+            panic!("Mara: the progress interval has to be at least 1");
+        }
+        let mut until_progress = interval;
+        for (index, block) in blocks.iter_mut().enumerate() {
+            let ptr = self.dynamic_new_bounded(size_in_byte, usize::MAX);
+            if ptr.is_null() {
+                return index;
+            }
+            *block = ptr;
+            until_progress -= 1;
+            if until_progress == 0 {
+                until_progress = interval;
+                if !progress(index + 1) {
+                    return index + 1;
+                }
+            }
+        }
+        blocks.len()
+    }
+
+    /// Like ``dynamic_new`` but reports a failed allocation as error.
+    /// #### size_in_byte
+    /// how many bytes shall be reserved
+    /// #### return
+    /// a pointer to the first byte in a reserved space with at least the requested size,
+    /// ``MaraError::ExceedsMaxAllocation`` if the request can never be satisfied
+    /// or ``MaraError::OutOfMemory`` with the size of the largest free block, so
+    /// the request can be repeated with a size that fits
+    pub fn try_dynamic_new(&self, size_in_byte: usize) -> Result<*mut u8, MaraError> {
+        if size_in_byte == 0 {
+            return Ok(globals::ZERO_SIZE_BLOCK as *mut u8);
+        }
+        let _lock = self.lock.lock();
+        let max_allocation = self.page_list().max_allocation_size();
+        if size_in_byte > max_allocation {
+            return Err(MaraError::ExceedsMaxAllocation { max_allocation });
+        }
+        let mut allocation_data = AllocationData::new();
+        allocation_data.space.set_size(size_in_byte);
+        let mut budget = usize::MAX;
+        self.page_list().dynamic_new(&mut allocation_data, &mut budget);
+        if allocation_data.space.is_some() {
+            Ok(allocation_data.space.ptr())
+        } else {
+            // still locked, so no other thread changed the heap since the search
+            Err(MaraError::OutOfMemory {
+                largest_available: self.page_list().largest_free_block(),
+            })
+        }
+    }
+
+    /// The size of the largest block that could be allocated in an empty heap.
+    /// Larger requests fail regardless of how much memory is freed.
+    pub fn max_allocation_size(&self) -> usize {
+        let _lock = self.lock.lock();
+        self.page_list().max_allocation_size()
+    }
+
+    /// The bytes a block of ``size_in_byte`` bytes takes in addition to its
+    /// space: the code blocks and the rounding of tiny requests up to
+    /// ``SMALLEST_POSSIBLE_SPACE``.
+    pub fn overhead_for(size_in_byte: usize) -> usize {
+        let space_size = size_in_byte.max(globals::SMALLEST_POSSIBLE_SPACE);
+        space_size - size_in_byte + 2 * code_block::get_needed_code_block_size(space_size)
+    }
+
+    /// Whether an empty heap could hold all allocations of ``profile`` at
+    /// the same time, assuming no fragmentation.
+    /// Each block takes its size and ``overhead_for`` it, the blocks are
+    /// packed without gaps into the dynamic sectors of all pages.
+    /// A size that exceeds ``max_allocation_size`` never fits.
+    /// #### profile
+    /// pairs of an allocation size and the number of such allocations
+    pub fn can_fit(&self, profile: &[(usize, usize)]) -> bool {
+        let _lock = self.lock.lock();
+        let max_allocation_size = self.page_list().max_allocation_size();
+        let mut needed: usize = 0;
+        for &(size, count) in profile {
+            if count == 0 {
+                continue;
+            }
+            if size > max_allocation_size {
+                return false;
+            }
+            let block_size = size + Self::overhead_for(size);
+            needed = needed.saturating_add(block_size.saturating_mul(count));
+        }
+        needed <= self.page_list().dynamic_capacity()
+    }
+
+    /// The size of the largest block that can currently be allocated,
+    /// 0 if the heap is full.
+    /// A used block keeps the code blocks of the free block it was taken
+    /// from, so a request of exactly this size succeeds.
+    pub fn largest_free_block(&self) -> usize {
+        let _lock = self.lock.lock();
+        self.page_list().largest_free_block()
+    }
+
+    /// The sum of all free spaces, without the code blocks.
+    /// Unlike ``largest_free_block`` this shows how full a fragmented heap
+    /// is, a single allocation of this size usually fails.
+    /// The free lists of all pages are walked, nothing is changed.
+    pub fn total_free_bytes(&self) -> usize {
+        let _lock = self.lock.lock();
+        self.page_list().total_free_bytes()
+    }
+
+    /// The external fragmentation, ``1 - largest_free_block / total_free_bytes``.
+    /// 0 if all free memory is in one block or nothing is free, close to 1
+    /// if there is a lot of free memory but no large block.
+    pub fn fragmentation(&self) -> f64 {
+        let _lock = self.lock.lock();
+        let page_list = self.page_list();
+        let total = page_list.total_free_bytes();
+        if total == 0 {
+            return 0.0;
+        }
+        1.0 - page_list.largest_free_block() as f64 / total as f64
+    }
+
+    /// Reserves zeroed memory in the dynamic sector that is aligned to ``align``.
+    /// Blocks from ``dynamic_new`` are only byte aligned, this searches all free
+    /// spaces for one that can hold the block at an aligned position.
+    /// The block is freed with ``dynamic_delete``.
+    /// #### size_in_byte
+    /// how many bytes shall be reserved and zeroed
+    /// #### align
+    /// alignment of the returned pointer, has to be a power of two,
+    /// alignments above ``MAX_ALIGN`` (4 KiB) cannot be served
+    /// #### return
+    /// a pointer to the first byte of the zeroed space or null if no free space fits.
+    /// A request of 0 bytes returns the pointer of ``dynamic_new(0)``.
+    pub fn alloc_aligned_zeroed(&self, size_in_byte: usize, align: usize) -> *mut u8 {
+        let ptr = self.alloc_aligned(size_in_byte, align);
+        if !ptr.is_null() {
+            unsafe { core::ptr::write_bytes(ptr, 0, size_in_byte) };
+        }
+        ptr
+    }
+
+    /// Like ``alloc_aligned_zeroed`` but the space is not zeroed
+    fn alloc_aligned(&self, size_in_byte: usize, align: usize) -> *mut u8 {
+        if size_in_byte == 0 {
+            if let Some(ptr) = Self::zero_size_block(align) {
+                return ptr;
+            }
+        }
+        let _lock = self.lock.lock();
+        let mut allocation_data = AllocationData::new();
+        allocation_data.space.set_size(size_in_byte);
+        self.page_list().aligned_new(&mut allocation_data, align);
+        if allocation_data.space.is_some() {
+            allocation_data.space.ptr()
+        } else {
+            core::ptr::null_mut()
+        }
+    }
+
+    /// Reserves memory in the dynamic sector and returns it with its real length,
+    /// which might be larger than requested.
+    /// The block is aligned to the alignment of ``layout`` like the blocks
+    /// of ``alloc_aligned_zeroed``, it is freed with ``dynamic_delete``.
+    /// None is returned if no free space is large enough.
+    /// A zero sized request returns an empty slice at the pointer of ``dynamic_new(0)``.
+    pub fn allocate_slice(&self, layout: Layout) -> Option<NonNull<[u8]>> {
+        if layout.size() == 0 {
+            let ptr = Self::zero_size_block(layout.align())?;
+            return NonNull::new(core::ptr::slice_from_raw_parts_mut(ptr, 0));
+        }
+        let _lock = self.lock.lock();
+        let mut allocation_data = AllocationData::new();
+        allocation_data.space.set_size(layout.size());
+        self.page_list().aligned_new(&mut allocation_data, layout.align());
+        if !allocation_data.space.is_some() {
+            return None;
+        }
+        let ptr = allocation_data.space.ptr();
+        let len = self.page_list().usable_size(ptr);
+        NonNull::new(core::ptr::slice_from_raw_parts_mut(ptr, len))
+    }
+
+    /// Moves all allocations to the start of their page, so that the free memory
+    /// of each page forms a single block. The content of the blocks is kept.
+    /// Pinned allocations and excluded ranges stay in place, the allocations
+    /// behind them are only moved up to them.
+    /// #### update
+    /// called with the old and the new pointer of every moved block,
+    /// every reference to the old location has to be updated.
+    /// It must not allocate or free with this Mara.
+    pub fn compact(&self, update: impl FnMut(*mut u8, *mut u8)) {
+        let _lock = self.lock.lock();
+        self.page_list().compact(update)
+    }
+
+    /// Takes a free block from exactly one size class in constant time.
+    /// Neither larger buckets are searched nor is the block split, so it
+    /// can be larger than the smallest size of its bucket.
+    /// Meant for pools of blocks of a fixed size, the bucket of a size is
+    /// returned by ``bucket_index``.
+    /// #### bucket_index
+    /// index of the bucket the block is taken from
+    /// #### return
+    /// a pointer to the space of the block or None if the bucket is empty
+    pub fn alloc_from_bucket(&self, bucket_index: usize) -> Option<*mut u8> {
+        let _lock = self.lock.lock();
+        let mut allocation_data = AllocationData::new();
+        self.page_list().bucket_new(&mut allocation_data, bucket_index);
+        if allocation_data.space.is_some() {
+            Some(allocation_data.space.ptr())
+        } else {
+            None
+        }
+    }
+
+    /// The index of the bucket that holds free blocks of ``size_in_byte`` bytes
+    pub fn bucket_index(size_in_byte: usize) -> usize {
+        bucket_list::BucketList::lookup_bucket(size_in_byte)
+    }
+
+    /// Moves an allocation to the free space with the lowest address in front
+    /// of it to compact the heap. The content of the block is copied and the
+    /// old block is freed, so every reference to it has to be updated.
+    /// #### ptr
+    /// a pointer that was returned by ``dynamic_new``
+    /// #### new_ptr
+    /// is set to the new location of the block if it was moved
+    /// #### return
+    /// false if there is no lower free space that fits or the block is
+    /// pinned, the block stays in place
+    pub fn relocate(&self, ptr: *mut u8, new_ptr: &mut *mut u8) -> bool {
+        let _lock = self.lock.lock();
+        match self.page_list().relocate(ptr) {
+            Some(relocated) => {
+                *new_ptr = relocated;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Pins an allocation, so that ``compact`` and ``relocate`` never move it,
+    /// e.g. while its address is held by foreign code.
+    /// Freeing the block removes the pin, ``dynamic_resize`` can still move it.
+    /// #### ptr
+    /// a pointer that was returned by ``dynamic_new``
+    /// #### return
+    /// false if ``ptr`` is no allocation or its page already holds 8 pinned
+    /// allocations and excluded ranges
+    pub fn pin(&self, ptr: *mut u8) -> bool {
+        let _lock = self.lock.lock();
+        self.page_list().pin(ptr)
+    }
+
+    /// Lets ``compact`` and ``relocate`` move a pinned allocation again.
+    /// False if ``ptr`` was not pinned.
+    pub fn unpin(&self, ptr: *mut u8) -> bool {
+        let _lock = self.lock.lock();
+        self.page_list().unpin(ptr)
+    }
+
+    /// The number of bytes that can be used in the block of ``ptr``.
+    /// This is at least the requested size.
+    /// #### ptr
+    /// a pointer that was returned by ``dynamic_new``
+    pub fn usable_size(&self, ptr: *mut u8) -> usize {
+        let _lock = self.lock.lock();
+        self.page_list().usable_size(ptr)
+    }
+
+    /// Frees every dynamic block at once, the cost is linear in the number
+    /// of pages. Afterwards each page holds a single free block like after
+    /// ``new``.
+    /// All pointers that were returned by ``dynamic_new`` and its variants
+    /// become invalid and must not be freed anymore.
+    /// Static blocks, the user data, excluded ranges and the settings like
+    /// the insertion policy are kept.
+    pub fn reset(&self) {
+        let _lock = self.lock.lock();
+        self.page_list().reset();
+    }
+
+    /// True if ``ptr`` was returned by ``dynamic_new`` and is not freed yet,
+    /// so it can be passed to ``dynamic_delete``.
+    /// Pointers into other memory, into the middle of a block or to static
+    /// blocks return false.
+    /// The cost is linear in the number of blocks of the page.
+    pub fn owns(&self, ptr: *mut u8) -> bool {
+        let _lock = self.lock.lock();
+        self.page_list().allocation_data(ptr).is_some()
+    }
+
+    /// Calls ``callback`` with the pointer and the usable size of every block
+    /// that is handed out, e.g. to trace the allocations or to build a leak
+    /// detector. A block that is resized in place is reported as freed with
+    /// its old size and handed out again with its new size.
+    /// None removes the callback.
+    /// The callback runs while the heap is locked and must not call into Mara.
+    pub fn on_alloc(&self, callback: Option<fn(*mut u8, usize)>) {
+        let _lock = self.lock.lock();
+        self.page_list().set_on_alloc(callback)
+    }
+
+    /// Like ``on_alloc`` but ``callback`` is called with every block that is
+    /// freed, with the size the block had before it was merged
+    pub fn on_free(&self, callback: Option<fn(*mut u8, usize)>) {
+        let _lock = self.lock.lock();
+        self.page_list().set_on_free(callback)
+    }
+
+    /// Selects where freed blocks are added to the free list of their size.
+    /// The default is ``InsertionPolicy::Lifo``.
+    /// The order of blocks that are already free is not changed.
+    pub fn set_insertion_policy(&self, insertion_policy: InsertionPolicy) {
+        let _lock = self.lock.lock();
+        self.page_list().set_insertion_policy(insertion_policy)
+    }
+
+    /// Selects how a free block is picked from the free list of its size.
+    /// The default is ``FitPolicy::FirstFit``.
+    pub fn set_fit_policy(&self, fit_policy: FitPolicy) {
+        let _lock = self.lock.lock();
+        self.page_list().set_fit_policy(fit_policy)
+    }
+
+    /// Selects which page is tried first for new dynamic blocks.
+    /// The default is ``PagePolicy::FirstPage``.
+    /// ``PagePolicy::MostFull`` packs the blocks into few pages so the others
+    /// stay empty, ``PagePolicy::LeastFull`` spreads them over all pages.
+    pub fn set_page_policy(&self, page_policy: PagePolicy) {
+        let _lock = self.lock.lock();
+        self.page_list().set_page_policy(page_policy)
+    }
+
+    /// The page policy that is currently used, see ``set_page_policy``
+    pub fn page_policy(&self) -> PagePolicy {
+        let _lock = self.lock.lock();
+        self.page_list().page_policy()
+    }
+
+    /// Replaces the fit policy, the coalesce threshold and the insertion
+    /// policy of all pages at once, e.g. to compare strategies on the same
+    /// live heap. The heap stays valid, only later allocations and frees
+    /// follow the new strategy.
+    pub fn set_strategy(&self, strategy: AllocStrategy) {
+        let _lock = self.lock.lock();
+        self.page_list().set_strategy(strategy)
+    }
+
+    /// The strategy that is currently used, see ``set_strategy``
+    pub fn strategy(&self) -> AllocStrategy {
+        let _lock = self.lock.lock();
+        self.page_list().strategy()
+    }
+
+    /// Marks a range of the data array as permanently off-limits, e.g. for reserved
+    /// headers or guard pages in a memory mapped file. Mara will never hand out
+    /// memory from this range or write into it.
+    /// The range cannot be freed, is not moved by ``compact`` and survives ``reset``.
+    /// Must be called before the first allocation. The range needs a few bytes
+    /// of free memory around it to store the code blocks that fence it.
+    /// A page holds at most 8 excluded ranges and pinned allocations together.
+    /// #### start
+    /// first byte of the range
+    /// #### len
+    /// length of the range in bytes
+    pub fn exclude_range(&self, start: *mut u8, len: usize) {
+        let _lock = self.lock.lock();
+        self.page_list().exclude_range(start, len)
+    }
+
+    /// All blocks, free and used, in the order they are placed in memory.
+    /// The heap is locked until the iterator is dropped, allocating or
+    /// freeing with this Mara in the meantime deadlocks with ``sync``.
+    pub fn iter_blocks(&self) -> impl Iterator<Item = BlockInfo> + '_ {
+        let lock = self.lock.lock();
+        let blocks = self.page_list().blocks();
+        Locked::new(lock, blocks.map(|block| BlockInfo::from(&block)))
+    }
+
+    /// All free blocks as the allocator sees them, by following the free
+    /// lists of the buckets. On a healthy heap these are exactly the free
+    /// blocks of ``iter_blocks``.
+    /// Like ``iter_blocks`` the heap is locked until the iterator is dropped.
+    pub fn iter_free_spaces(&self) -> impl Iterator<Item = BlockInfo> + '_ {
+        let lock = self.lock.lock();
+        let free_blocks = self.page_list().free_blocks();
+        Locked::new(lock, free_blocks.map(|block| BlockInfo::from(&block)))
+    }
+
+    /// Captures the current layout of the heap.
+    /// Compare two snapshots with ``HeapSnapshot::diff`` to see which
+    /// blocks were allocated, freed or resized in between.
+    /// The blocks are collected while the heap is locked, so a Mara cannot
+    /// take a snapshot of itself while it is the global allocator.
+    #[cfg(not(feature = "no_std"))]
+    pub fn snapshot(&self) -> HeapSnapshot {
+        HeapSnapshot::new(self.iter_blocks().collect())
+    }
+
+    /// The internal representation of the used block at ``ptr`` with its
+    /// data range, code block size and space.
+    /// Meant for extensions that work on the heap layout directly.
+    /// ``AllocationData`` is an implementation detail, its fields and methods
+    /// may change in any release.
+    /// None if ``ptr`` is not the space of a used block.
+    /// # Safety
+    /// The returned data describes the block at the time of the call only.
+    /// Writing through it bypasses all bookkeeping of Mara and can corrupt
+    /// the heap.
+    pub unsafe fn allocation_data(&self, ptr: *mut u8) -> Option<AllocationData> {
+        let _lock = self.lock.lock();
+        self.page_list().allocation_data(ptr)
+    }
+
+    /// The blocks that physically precede and succeed the block at ``ptr``.
+    /// None if the block is the first or last one in its page.
+    /// #### ptr
+    /// a pointer that was returned by ``dynamic_new``
+    pub fn neighbor_blocks(&self, ptr: *mut u8) -> (Option<BlockInfo>, Option<BlockInfo>) {
+        let _lock = self.lock.lock();
+        self.page_list().neighbor_blocks(ptr)
+    }
+
+    /// Walks the free lists and checks that every entry is a free block in
+    /// the bucket of its size. Meant for debugging, the cost is linear in
+    /// the number of free blocks.
+    /// #### return
+    /// the first corruption that was found
+    pub fn verify(&self) -> Result<(), MaraError> {
+        let _lock = self.lock.lock();
+        self.page_list().verify()
+    }
+
+    /// The number of bytes that are used by code blocks, summed over all
+    /// free and used blocks.
+    /// Small allocations carry a large overhead in relation to their size.
+    pub fn code_block_overhead_bytes(&self) -> usize {
+        let _lock = self.lock.lock();
+        self.page_list().code_block_overhead_bytes()
+    }
+
+    /// How many steps the free space search took for the allocations so far.
+    /// A high average means that the size classes do not match the workload
+    /// or that the free lists are long.
+    #[cfg(feature = "statistic")]
+    pub fn search_stats(&self) -> SearchStats {
+        let _lock = self.lock.lock();
+        self.page_list().search_stats()
+    }
+
+    /// The usage counters of the dynamic sector
+    #[cfg(feature = "statistic")]
+    pub fn statistics(&self) -> Statistics {
+        let _lock = self.lock.lock();
+        self.page_list().statistics()
+    }
+
+    /// Where the time of the allocations and frees was spent: in merging freed
+    /// blocks, in updating the buckets or in searching a fitting free space.
+    /// Tells if a workload is bound by coalescing or by the search.
+    /// The durations are only measured with ``std``.
+    #[cfg(feature = "statistic")]
+    pub fn timing_breakdown(&self) -> TimingBreakdown {
+        let _lock = self.lock.lock();
+        self.page_list().timing_breakdown()
+    }
+
+    /// Clears the cumulative counters (allocations, frees, search steps,
+    /// timings and internal waste) to measure intervals. The live state like
+    /// ``allocated_bytes`` and the peaks are kept.
+    #[cfg(feature = "statistic")]
+    pub fn reset_statistics(&self) {
+        let _lock = self.lock.lock();
+        self.page_list().reset_statistics()
+    }
+
+    /// The longest free list each bucket ever had, indexed by bucket.
+    /// Long chains point to a size class that does not fit the workload
+    /// or to blocks that could not be merged.
+    #[cfg(feature = "statistic")]
+    pub fn max_chain_lengths(&self) -> [usize; globals::BUCKET_LIST_SIZE] {
+        let _lock = self.lock.lock();
+        self.page_list().max_chain_lengths()
+    }
+
+    /// The size classes that were ever used, bit ``i`` is set if a block of
+    /// bucket ``i`` was allocated or freed. The mask is never reset.
+    /// Buckets that stay untouched for a workload hint at a bucket geometry
+    /// that could be smaller.
+    #[cfg(feature = "statistic")]
+    pub fn touched_buckets(&self) -> u64 {
+        let _lock = self.lock.lock();
+        self.page_list().touched_buckets()
+    }
+
+    /// Sets the peaks (including the chain lengths) to the current state
+    #[cfg(feature = "statistic")]
+    pub fn reset_peaks(&self) {
+        let _lock = self.lock.lock();
+        self.page_list().reset_peaks()
     }
 
     /**
      * frees a previously reserved space in the dynamic sector
      * @param address the pointer that was returned by dynamicNew
-     * @return true if the operation was successful, false elsewhen
+     * panics if the pointer is not the start of a block, see ``try_dynamic_delete``
      */
     pub fn dynamic_delete(&self, address: *mut u8) {
+        if let Err(error) = self.try_dynamic_delete(address) {
+            panic!("Mara: {}", error);
+        }
+    }
+
+    /// The pointer that is returned for requests of 0 bytes, it is aligned
+    /// to every alignment up to ``MAX_ALIGN``. None for larger alignments.
+    fn zero_size_block(align: usize) -> Option<*mut u8> {
+        if align.is_power_of_two() && align <= globals::MAX_ALIGN {
+            Some(globals::ZERO_SIZE_BLOCK as *mut u8)
+        } else {
+            None
+        }
+    }
+
+    /// Like ``dynamic_delete`` but reports an invalid pointer as error.
+    /// #### address
+    /// a pointer that was returned by ``dynamic_new``
+    /// #### return
+    /// ``MaraError::InvalidFree`` if ``address`` is not the start of a block
+    /// in any page, e.g. a pointer into the middle of a block, or
+    /// ``MaraError::DoubleFree`` if the block was freed before,
+    /// the heap is unchanged in these cases.
+    /// The blocks in front of ``address`` are walked to tell it from a
+    /// pointer into a block, so the cost is linear in the number of blocks.
+    pub fn try_dynamic_delete(&self, address: *mut u8) -> Result<(), MaraError> {
+        if address as usize == globals::ZERO_SIZE_BLOCK {
+            return Ok(());
+        }
+        let _lock = self.lock.lock();
         self.page_list().dynamic_delete(address)
     }
 }
 
+/// Requests with an alignment above 1 take the path of ``alloc_aligned_zeroed``,
+/// blocks of ``dynamic_new`` are only byte aligned.
 unsafe impl GlobalAlloc for Mara {
     unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
-        self.dynamic_new(layout.size())
+        if layout.align() == 1 {
+            self.dynamic_new(layout.size())
+        } else {
+            self.alloc_aligned(layout.size(), layout.align())
+        }
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        if layout.align() == 1 {
+            self.dynamic_new_zeroed(layout.size())
+        } else {
+            self.alloc_aligned_zeroed(layout.size(), layout.align())
+        }
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        if ptr as usize == globals::ZERO_SIZE_BLOCK {
+            return self.alloc(Layout::from_size_align_unchecked(new_size, layout.align()));
+        }
+        let _lock = self.lock.lock();
+        self.page_list()
+            .resize(ptr, new_size, layout.align())
+            .unwrap_or(core::ptr::null_mut())
     }
 
     unsafe fn dealloc(&self, ptr: *mut u8, _layout: Layout) {
         self.dynamic_delete(ptr);
     }
 }
+
+/// With ``consistency-checks`` the blocks that are still in use are counted
+/// and reported on stderr, without the feature nothing is done.
+/// The memory is not freed, Mara does not own it.
+impl Drop for Mara {
+    fn drop(&mut self) {
+        self.page_list.get_mut().check_leaks();
+    }
+}
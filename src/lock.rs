@@ -0,0 +1,84 @@
+//! Serializes the changes of the page list with the ``sync`` feature.
+//! Without the feature locking does nothing.
+
+#[cfg(feature = "sync")]
+use core::sync::atomic::{AtomicBool, Ordering};
+
+/// A spin lock that works without an operating system
+pub struct Lock {
+    #[cfg(feature = "sync")]
+    locked: AtomicBool,
+}
+
+/// Releases the lock when it is dropped
+pub struct LockGuard<'a> {
+    #[cfg(feature = "sync")]
+    lock: &'a Lock,
+    #[cfg(not(feature = "sync"))]
+    lock: core::marker::PhantomData<&'a Lock>,
+}
+
+impl Lock {
+    pub const fn new() -> Self {
+        Self {
+            #[cfg(feature = "sync")]
+            locked: AtomicBool::new(false),
+        }
+    }
+    /// Waits until no other thread holds the lock.
+    /// The lock is not reentrant, taking it twice on the same thread
+    /// deadlocks.
+    #[inline]
+    pub fn lock(&self) -> LockGuard<'_> {
+        #[cfg(feature = "sync")]
+        {
+            while self
+                .locked
+                .compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed)
+                .is_err()
+            {
+                while self.locked.load(Ordering::Relaxed) {
+                    core::hint::spin_loop();
+                }
+            }
+            LockGuard { lock: self }
+        }
+        #[cfg(not(feature = "sync"))]
+        {
+            LockGuard {
+                lock: core::marker::PhantomData,
+            }
+        }
+    }
+}
+
+impl Drop for LockGuard<'_> {
+    #[inline]
+    fn drop(&mut self) {
+        #[cfg(feature = "sync")]
+        self.lock.locked.store(false, Ordering::Release);
+    }
+}
+
+/// Holds the lock while an iterator over the heap is alive
+pub struct Locked<'a, I> {
+    iter: I,
+    _guard: LockGuard<'a>,
+}
+
+impl<'a, I: Iterator> Locked<'a, I> {
+    /// ``iter`` has to be created while ``guard`` is held
+    pub fn new(guard: LockGuard<'a>, iter: I) -> Self {
+        Self {
+            iter,
+            _guard: guard,
+        }
+    }
+}
+
+impl<I: Iterator> Iterator for Locked<'_, I> {
+    type Item = I::Item;
+    fn next(&mut self) -> Option<I::Item> {
+        self.iter.next()
+    }
+}
@@ -1,9 +1,11 @@
+use crate::block_info::BlockInfo;
 use crate::bucket_list::BucketList;
 use crate::code_block;
+use crate::error::MaraError;
 use crate::globals::*;
 use crate::space::Space;
+use crate::strategy::AllocStrategy;
 use crate::AllocationData;
-use core::mem::size_of;
 use core::ops::*;
 
 /// The instance that stores the allocations.
@@ -12,6 +14,9 @@ use core::ops::*;
 /// The space that is free to allocate in the future is managed in the
 /// bucket list.
 pub struct Page {
+    /// ``PAGE_CANARY`` while the page struct is intact
+    #[cfg(feature = "canary")]
+    canary: usize,
     /// Pointer to the first byte of the page
     start_of_page: *const u8,
     /// Pointer to the next page
@@ -20,16 +25,108 @@ pub struct Page {
     /// the rightmost byte is the last byte of the page
     end_of_page: *const u8,
     bucket_list: BucketList,
+    /// Number of blocks that are handed out
+    used_block_count: usize,
+    /// Sum of the space sizes of the handed out blocks
+    used_bytes: usize,
+    /// Number of bytes in front of the first block that are reserved for the user
+    user_data_size: usize,
+    /// Space size of a block that spans the whole page
+    max_payload_size: usize,
+    /// Freed blocks with a smaller space are not merged with their neighbors
+    coalesce_threshold: usize,
+    /// Fills the free spaces behind their next pointer
+    #[cfg(feature = "poison")]
+    poison_byte: u8,
+    /// The lowest byte of the static sector, which grows down from the end
+    /// of the page. The dynamic blocks end right below it at ``end_of_page``.
+    end_of_static: *mut u8,
+    /// Blocks that are never moved, the first ``pin_count`` entries are used
+    pins: [Pin; MAX_PINNED_BLOCKS],
+    pin_count: usize,
+    /// One free flag for every ``SMALLEST_POSSIBLE_FREE_SPACE`` bytes of the
+    /// page, placed in front of the user data, see ``free_flag``
+    #[cfg(feature = "free_map")]
+    free_map: *mut u8,
+}
+
+/// A used block that ``compact`` and ``relocate`` never move
+#[derive(Copy, Clone)]
+struct Pin {
+    /// The space of the block
+    space: *mut u8,
+    /// Start and length of the range given to ``exclude_range``.
+    /// The length is 0 for pinned allocations.
+    excluded: (*mut u8, usize),
+}
+
+impl Pin {
+    const UNUSED: Pin = Pin {
+        space: core::ptr::null_mut(),
+        excluded: (core::ptr::null_mut(), 0),
+    };
+    /// Excluded blocks were never handed out and can never be freed
+    fn is_excluded(&self) -> bool {
+        self.excluded.1 != 0
+    }
+}
+
+/// The page size is limited on creation so that every offset fits in a next pointer
+const OFFSET_OVERFLOW: &str = "Mara: free space offset does not fit in a next pointer";
+
+/// Walks the blocks of a page by following the code blocks, from
+/// ``start_of_page`` until a block ends at ``end_of_page``.
+/// The code blocks of each block are cached.
+pub struct Blocks {
+    next: Option<AllocationData>,
+}
+
+impl Iterator for Blocks {
+    type Item = AllocationData;
+    fn next(&mut self) -> Option<AllocationData> {
+        let block = self.next.take()?;
+        self.next = block.right_neighbor();
+        Some(block)
+    }
 }
 
 impl Page {
-    pub fn init(&mut self, page_memory: *mut u8, page_size: usize) {
+    /// Sets up a page that manages ``page_size`` bytes starting at ``page_memory``.
+    /// The first ``user_data_size`` bytes are reserved for ``user_data``,
+    /// the blocks are placed behind them. With ``free_map`` the free map
+    /// is placed in front of the user data.
+    pub fn init(&mut self, page_memory: *mut u8, page_size: usize, user_data_size: usize) {
+        let free_map_size = Self::free_map_size(page_size.saturating_sub(user_data_size));
+        if user_data_size + free_map_size + SMALLEST_POSSIBLE_FREE_SPACE > page_size {
+            panic!("Mara: no room for {} bytes of user data", user_data_size);
+        }
+        #[cfg(feature = "free_map")]
+        {
+            self.free_map = page_memory;
+            unsafe { core::ptr::write_bytes(page_memory, 0, free_map_size) };
+        }
+        self.user_data_size = user_data_size;
+        let page_memory = unsafe { page_memory.add(free_map_size + user_data_size) };
+        let page_size = page_size - free_map_size - user_data_size;
         unsafe {
             let this = self as *mut Page;
+            #[cfg(feature = "canary")]
+            {
+                self.canary = PAGE_CANARY;
+            }
             self.next_page = core::ptr::null_mut();
             self.start_of_page = page_memory;
             self.end_of_page = page_memory.add(page_size).sub(1);
-            code_block::set_free(page_memory, true);
+            self.end_of_static = page_memory.add(page_size);
+            self.used_block_count = 0;
+            self.used_bytes = 0;
+            self.coalesce_threshold = 0;
+            #[cfg(feature = "poison")]
+            {
+                self.poison_byte = POISON_BYTE;
+            }
+            self.pins = [Pin::UNUSED; MAX_PINNED_BLOCKS];
+            self.pin_count = 0;
             self.bucket_list.init(this);
             let mut alloc_data = AllocationData::new();
             alloc_data.set_data_start(page_memory);
@@ -37,8 +134,10 @@ impl Page {
             alloc_data.set_page(self);
             alloc_data.write_data_size_code_blocks(true);
             alloc_data.space.set_next(None);
-            alloc_data.space.write_next(self.start_of_page);
-            self.bucket_list.insert(&mut alloc_data.space);
+            alloc_data.space.write_next(self.start_of_page).expect(OFFSET_OVERFLOW);
+            self.bucket_list.insert(&mut alloc_data.space).expect(OFFSET_OVERFLOW);
+            self.poison(&alloc_data.space);
+            self.max_payload_size = alloc_data.space.size();
 
             self.check_integrity();
             self.bucket_list().check_init();
@@ -47,24 +146,105 @@ impl Page {
             alloc_data.check_data_size(page_size, page_size);
         }
     }
+    /// Turns the whole dynamic sector into a single free block again.
+    /// The user data, the static sector and the settings of the page are kept.
+    /// Excluded ranges are carved out again.
+    pub fn reset(&mut self) {
+        self.check_canary();
+        let pins = self.pins;
+        let pin_count = self.pin_count;
+        self.pin_count = 0;
+        unsafe {
+            self.used_block_count = 0;
+            self.used_bytes = 0;
+            self.bucket_list.clear();
+            let mut alloc_data = AllocationData::new();
+            alloc_data.set_data_start(self.start_of_page as *mut u8);
+            alloc_data.set_data_end(self.end_of_page as *mut u8);
+            alloc_data.set_page(self);
+            alloc_data.write_data_size_code_blocks(true);
+            alloc_data.space.set_next(None);
+            alloc_data.space.write_next(self.start_of_page).expect(OFFSET_OVERFLOW);
+            self.bucket_list.insert(&mut alloc_data.space).expect(OFFSET_OVERFLOW);
+
+            self.check_integrity();
+            alloc_data.check_consistency();
+        }
+        for pin in pins[..pin_count].iter().filter(|pin| pin.is_excluded()) {
+            self.exclude_range(pin.excluded.0, pin.excluded.1);
+        }
+        // the excluded ranges must not be poisoned
+        for free_alloc in self.free_blocks() {
+            unsafe { self.poison(&free_alloc.space) };
+        }
+    }
+    /// Carves ``size`` bytes off the end of the page for the static sector.
+    /// Static blocks have no code blocks and are never freed.
+    /// The last block of the page has to be free and keep at least
+    /// ``SMALLEST_POSSIBLE_FREE_SPACE`` bytes, otherwise None is returned.
+    pub fn get_static_block(&mut self, size: usize) -> Option<*mut u8> {
+        unsafe {
+            self.check_integrity();
+            let mut last_alloc = AllocationData::new();
+            last_alloc.set_page(self);
+            last_alloc.set_data_end(self.end_of_page as *mut u8);
+            last_alloc.cache_code_blocks();
+            if !last_alloc.is_free()
+                || last_alloc.calculate_data_size() < size + SMALLEST_POSSIBLE_FREE_SPACE
+            {
+                return None;
+            }
+            last_alloc.space.cache_next(self.start_of_page);
+            self.bucket_list.remove(&last_alloc.space).expect(OFFSET_OVERFLOW);
+            let old_space = last_alloc.space;
+            self.end_of_static = self.end_of_static.sub(size);
+            self.end_of_page = self.end_of_static.sub(1);
+            // the rest of the free block ends at the new end of the page
+            last_alloc.set_data_end(self.end_of_page as *mut u8);
+            last_alloc.write_data_size_code_blocks(true);
+            self.bucket_list.insert(&mut last_alloc.space).expect(OFFSET_OVERFLOW);
+            self.repoison(&last_alloc.space, &old_space);
+            self.max_payload_size = code_block::space_size_for_internal_size(self.page_size());
+            last_alloc.check_consistency();
+            self.check_integrity();
+            Some(self.end_of_static)
+        }
+    }
     /// Tries to reserve a dynamic block in this page.
     /// if one is found the space pointer of the allocation will be set
     /// otherwise the pointer will be uninitialized
-    pub fn get_dynamic_block(&mut self, alloc_data: &mut AllocationData) {
+    /// The search and the split are aborted if they need more steps than
+    /// ``budget`` allows.
+    pub fn get_dynamic_block(&mut self, alloc_data: &mut AllocationData, budget: &mut usize) {
+        self.check_canary();
         unsafe {
             alloc_data.set_page(self);
-            alloc_data.space.check_size(1, self.page_size());
+            alloc_data.space.check_size(1, self.max_payload_size());
             self.check_integrity();
+            self.bucket_list.check_largest_free();
+            // hopeless requests fail without a search, so the next page is tried
+            if alloc_data.space.size() > self.bucket_list.largest_free_size() {
+                return;
+            }
 
-            match self.bucket_list.get_free_space(alloc_data.space.size()) {
+            match self.bucket_list.get_free_space(alloc_data.space.size(), budget) {
                 None => {
                     self.check_integrity();
                     return;
                 }
+                // no step left for the split
+                Some(_) if *budget == 0 => {
+                    self.check_integrity();
+                    return;
+                }
                 Some(free_space) => {
+                    *budget -= 1;
+                    self.check_poison(&free_space, alloc_data.space.size());
+                    #[cfg(feature = "statistic")]
+                    self.bucket_list.record_touched(alloc_data.space.size());
                     // Remove this free space from list
                     // the remaining space will be added again later
-                    self.bucket_list.remove(&free_space);
+                    self.bucket_list.remove(&free_space).expect(OFFSET_OVERFLOW);
                     // Calculate where the allocation starts
                     // It will be at the beginning of the found free space
                     alloc_data.set_data_start(
@@ -76,13 +256,21 @@ impl Page {
                     let mut remaining = self.split_free_space(alloc_data, free_space);
                     // check if no space remains
                     if remaining.space.size() != 0 {
-                        self.bucket_list.insert(&mut remaining.space);
+                        self.bucket_list.insert(&mut remaining.space).expect(OFFSET_OVERFLOW);
+                        self.repoison(&remaining.space, &free_space);
                     } else {
                         // Edge Case: If the remaining space is too small to be used again,
-                        // simply return a larger block
-                        code_block::set_free(alloc_data.data_start(), false);
+                        // simply return a larger block.
+                        // Caching the code blocks sets the space size to the whole
+                        // free space, so the caller sees the size that was committed
                         alloc_data.cache_code_blocks();
-                        alloc_data.copy_code_block_to_end();
+                        alloc_data.set_free(false);
+                    }
+                    self.check_successor_preserved(&free_space);
+                    self.record_used(alloc_data);
+                    #[cfg(feature = "precommit_next_pointer")]
+                    {
+                        Self::precommit_next_pointer(alloc_data);
                     }
                 }
             }
@@ -92,6 +280,56 @@ impl Page {
             self.check_dynamic_new_post(alloc_data);
         }
     }
+    /// Writes the bytes where ``delete_block`` will store the next pointer.
+    /// The block is not handed out yet so its content does not matter.
+    #[cfg(feature = "precommit_next_pointer")]
+    #[inline]
+    unsafe fn precommit_next_pointer(alloc_data: &AllocationData) {
+        let location = alloc_data.next_pointer_location() as *mut u8;
+        for i in 0..core::mem::size_of::<NextPointerType>().min(alloc_data.space.size()) {
+            core::ptr::write_volatile(location.add(i), 0);
+        }
+    }
+    /// Fills a free space with the poison byte.
+    /// The next pointer at its start is kept for the bucket list.
+    /// Parts of a poisoned space that are split off stay poisoned.
+    #[inline]
+    #[allow(unused_variables)]
+    unsafe fn poison(&self, space: &Space) {
+        #[cfg(feature = "poison")]
+        {
+            let next_pointer_size = core::mem::size_of::<NextPointerType>();
+            core::ptr::write_bytes(
+                space.ptr().add(next_pointer_size),
+                self.poison_byte,
+                space.size() - next_pointer_size,
+            );
+        }
+    }
+    /// Poisons the parts of the free ``space`` that were not poisoned as
+    /// part of ``old``, the free space it was split from.
+    /// Those are the code blocks and the next pointer of ``old`` that end up
+    /// inside ``space`` if the code blocks shrink.
+    #[inline]
+    #[allow(unused_variables)]
+    unsafe fn repoison(&self, space: &Space, old: &Space) {
+        #[cfg(feature = "poison")]
+        {
+            let next_pointer_size = core::mem::size_of::<NextPointerType>();
+            let start = space.ptr().add(next_pointer_size);
+            let end = space.ptr().add(space.size());
+            let old_start = old.ptr().add(next_pointer_size);
+            let old_end = old.ptr().add(old.size());
+            let front_end = old_start.min(end);
+            if start < front_end {
+                core::ptr::write_bytes(start, self.poison_byte, front_end as usize - start as usize);
+            }
+            let back_start = old_end.max(start);
+            if back_start < end {
+                core::ptr::write_bytes(back_start, self.poison_byte, end as usize - back_start as usize);
+            }
+        }
+    }
     /// Splits ``free_space`` into two separate parts.
     /// ``alloc data`` will be the left side of the split,
     /// and the returned allocation will be the right side.
@@ -136,32 +374,535 @@ impl Page {
             free_alloc
         }
     }
+    /// Carves the ``len`` bytes starting at ``start`` permanently out of the page.
+    /// The range becomes the space of a block that is marked as used but never
+    /// handed out, so it is neither returned by ``get_dynamic_block`` nor
+    /// merged with its neighbors when they are freed.
+    /// The range has to lie in a single free space with enough room for the
+    /// surrounding code blocks. Remainders that are too small to be managed as
+    /// free space are added to the excluded block.
+    /// The block is recorded, so it is never freed or moved and survives ``reset``.
+    /// Expected to be called after ``init`` and before any allocation.
+    pub fn exclude_range(&mut self, start: *mut u8, len: usize) {
+        unsafe {
+            if len == 0 {
+                panic!("Mara: cannot exclude an empty range");
+            }
+            if self.pin_count == MAX_PINNED_BLOCKS {
+                panic!(
+                    "Mara: a page holds at most {} excluded ranges",
+                    MAX_PINNED_BLOCKS
+                );
+            }
+            let end = start.add(len - 1);
+            if (start as usize) < self.start_of_page as usize
+                || end as usize > self.end_of_page as usize
+            {
+                panic!("Mara: excluded range is outside of the page");
+            }
+            // find the free space that contains the range
+            let mut free_alloc = self
+                .blocks()
+                .find(|block| block.data_end() as usize >= start as usize)
+                .expect("Mara: excluded range is outside of the page");
+            if !free_alloc.is_free() || (free_alloc.data_end() as usize) < end as usize {
+                panic!("Mara: excluded range is not part of a single free space");
+            }
+            let (excluded_start, excluded_end, _) = place_range(&free_alloc, start, len)
+                .expect("Mara: no room for the code blocks around the excluded range");
+            let excluded = self.carve_range(&mut free_alloc, excluded_start, excluded_end);
+            self.pins[self.pin_count] = Pin {
+                space: excluded.space.ptr(),
+                excluded: (start, len),
+            };
+            self.pin_count += 1;
+
+            self.check_integrity();
+            excluded.check_left_free(false);
+            excluded.check_consistency();
+            excluded.check_neighbors();
+            self.check_alloc(&excluded);
+        }
+    }
+    /// Reserves a block whose space starts at a multiple of ``align``.
+    /// All free spaces are searched until one can hold the block at an aligned
+    /// position. The bytes in front of the block remain a free space if there
+    /// are enough of them, otherwise the next aligned position is tried.
+    /// If no free space fits, the space pointer of the allocation stays uninitialized.
+    pub fn get_aligned_block(&mut self, alloc_data: &mut AllocationData, align: usize) {
+        unsafe {
+            alloc_data.set_page(self);
+            alloc_data.space.check_size(1, self.max_payload_size());
+            self.check_integrity();
+            let size = alloc_data.space.size();
+            let code_block_size = code_block::get_needed_code_block_size(size);
+            let placement = self.free_blocks().find_map(|free_alloc| {
+                let free_start = free_alloc.data_start() as usize;
+                let mut start = align_up(free_start + code_block_size, align);
+                let remainder = start - code_block_size - free_start;
+                if remainder != 0 && remainder < SMALLEST_POSSIBLE_FREE_SPACE {
+                    start = align_up(start + SMALLEST_POSSIBLE_FREE_SPACE, align);
+                }
+                // larger code blocks would move the space away from the aligned position
+                match place_range(&free_alloc, start as *mut u8, size) {
+                    Some((excluded_start, excluded_end, placed_code_block_size))
+                        if placed_code_block_size == code_block_size
+                            && excluded_start as usize + code_block_size == start =>
+                    {
+                        Some((free_alloc, excluded_start, excluded_end))
+                    }
+                    _ => None,
+                }
+            });
+            if let Some((mut free_alloc, excluded_start, excluded_end)) = placement {
+                *alloc_data = self.carve_range(&mut free_alloc, excluded_start, excluded_end);
+                self.record_used(alloc_data);
+                #[cfg(feature = "precommit_next_pointer")]
+                {
+                    Self::precommit_next_pointer(alloc_data);
+                }
+                alloc_data.check_consistency();
+                alloc_data.check_neighbors();
+                self.check_dynamic_new_post(alloc_data);
+            }
+            self.check_integrity();
+        }
+    }
+    /// Reserves the first free space of the bucket with the given index as it
+    /// is, without a search or a split.
+    /// If the bucket is empty, the space pointer of the allocation stays uninitialized.
+    pub fn pop_bucket(&mut self, alloc_data: &mut AllocationData, index: usize) {
+        unsafe {
+            alloc_data.set_page(self);
+            self.check_integrity();
+            if let Some(mut space) = self.bucket_list.iter_bucket(index).next() {
+                space.cache_size_from_code_block();
+                space.cache_next(self.start_of_page);
+                self.bucket_list.remove(&space).expect(OFFSET_OVERFLOW);
+                alloc_data.space.set_ptr(space.ptr());
+                alloc_data.cache_code_blocks();
+                alloc_data.set_free(false);
+                self.record_used(alloc_data);
+                #[cfg(feature = "precommit_next_pointer")]
+                {
+                    Self::precommit_next_pointer(alloc_data);
+                }
+                alloc_data.check_consistency();
+                alloc_data.check_neighbors();
+                self.check_dynamic_new_post(alloc_data);
+            }
+            self.check_integrity();
+        }
+    }
+    /// Reserves a block in the free space with the lowest address that lies
+    /// in front of ``limit`` and is large enough.
+    /// If no free space fits, the space pointer of the allocation stays uninitialized.
+    pub fn get_lower_block(&mut self, alloc_data: &mut AllocationData, limit: *mut u8) {
+        unsafe {
+            alloc_data.set_page(self);
+            alloc_data.space.check_size(1, self.max_payload_size());
+            self.check_integrity();
+            let size = alloc_data.space.size();
+            let code_block_size = code_block::get_needed_code_block_size(size);
+            let placement = self
+                .free_blocks()
+                .filter(|free_alloc| (free_alloc.data_end() as usize) < limit as usize)
+                .filter_map(|free_alloc| {
+                    let start = free_alloc.data_start().add(code_block_size);
+                    let (excluded_start, excluded_end, _) =
+                        place_range(&free_alloc, start, size)?;
+                    Some((free_alloc, excluded_start, excluded_end))
+                })
+                .min_by_key(|(free_alloc, _, _)| free_alloc.data_start() as usize);
+            if let Some((mut free_alloc, excluded_start, excluded_end)) = placement {
+                *alloc_data = self.carve_range(&mut free_alloc, excluded_start, excluded_end);
+                self.record_used(alloc_data);
+                alloc_data.check_consistency();
+                alloc_data.check_neighbors();
+                self.check_dynamic_new_post(alloc_data);
+            }
+            self.check_integrity();
+        }
+    }
+    /// Moves all used blocks to the start of the page, so that the free
+    /// memory forms a single block at the end.
+    /// Pinned and excluded blocks stay in place, the blocks behind them are
+    /// moved up to them, so a free block can remain in front of each of them.
+    /// ``update`` is called with the old and the new pointer of every moved block.
+    pub fn compact(&mut self, mut update: impl FnMut(*mut u8, *mut u8)) {
+        unsafe {
+            self.check_integrity();
+            let mut block = self.blocks().next();
+            while let Some(mut current) = block {
+                if !current.is_free() && !self.is_pinned(current.space.ptr()) {
+                    if let Some(left) = current.left_neighbor() {
+                        if left.is_free() {
+                            let old_ptr = current.space.ptr();
+                            current = self.slide_left(current, left);
+                            update(old_ptr, current.space.ptr());
+                        }
+                    }
+                }
+                block = current.right_neighbor();
+            }
+            self.check_integrity();
+        }
+    }
+    /// Moves a used block to the start of the free block in front of it.
+    /// The free space ends up behind the moved block where it is merged
+    /// with a free right neighbor.
+    /// Returns the moved block.
+    unsafe fn slide_left(
+        &mut self,
+        block: AllocationData,
+        mut free_alloc: AllocationData,
+    ) -> AllocationData {
+        let size = block.space.size();
+        free_alloc.space.cache_next(self.start_of_page);
+        self.bucket_list.remove(&free_alloc.space).expect(OFFSET_OVERFLOW);
+        // the content overlaps its old location if the free block is smaller
+        let new_ptr = free_alloc
+            .data_start()
+            .add(code_block::get_needed_code_block_size(size));
+        core::ptr::copy(block.space.ptr(), new_ptr, size);
+        // the code blocks are written behind the copied content
+        let mut moved = AllocationData::new();
+        moved.set_page(self);
+        moved.set_data_start(free_alloc.data_start());
+        moved.space.set_size(size);
+        moved.write_space_size_code_blocks(false);
+        let mut rest = AllocationData::new();
+        rest.set_page(self);
+        rest.set_data_start(moved.data_end().add(1));
+        rest.set_data_end(block.data_end());
+        self.merge_with_neighbors(&mut rest);
+        moved.check_consistency();
+        moved.check_neighbors();
+        moved
+    }
+    /// Replaces ``free_alloc`` with a used block from ``excluded_start`` to
+    /// ``excluded_end`` and free spaces for the parts left and right of it.
+    /// The bounds are expected to be computed by ``place_range``.
+    /// Returns the used block.
+    unsafe fn carve_range(
+        &mut self,
+        free_alloc: &mut AllocationData,
+        excluded_start: *mut u8,
+        excluded_end: *mut u8,
+    ) -> AllocationData {
+        let free_start = free_alloc.data_start();
+        let free_end = free_alloc.data_end();
+        free_alloc.space.cache_next(self.start_of_page);
+        self.bucket_list.remove(&free_alloc.space).expect(OFFSET_OVERFLOW);
+        // free space left of the range
+        if excluded_start != free_start {
+            let mut left = AllocationData::new();
+            left.set_page(self);
+            left.set_data_start(free_start);
+            left.set_data_end(excluded_start.sub(1));
+            left.write_data_size_code_blocks(true);
+            self.bucket_list.insert(&mut left.space).expect(OFFSET_OVERFLOW);
+            self.repoison(&left.space, &free_alloc.space);
+            left.check_consistency();
+        }
+        // free space right of the range
+        if excluded_end != free_end {
+            let mut right = AllocationData::new();
+            right.set_page(self);
+            right.set_data_start(excluded_end.add(1));
+            right.set_data_end(free_end);
+            right.write_data_size_code_blocks(true);
+            self.bucket_list.insert(&mut right.space).expect(OFFSET_OVERFLOW);
+            self.repoison(&right.space, &free_alloc.space);
+            right.check_consistency();
+        }
+        let mut excluded = AllocationData::new();
+        excluded.set_page(self);
+        excluded.set_data_start(excluded_start);
+        excluded.set_data_end(excluded_end);
+        excluded.write_data_size_code_blocks(false);
+        excluded
+    }
     /// Deletes a reserved block and adds it into the bucket list again.
     /// If the neighboring spaces are free they are merged wit this space.
-    pub fn delete_block(&mut self, alloc_data: &mut AllocationData) {
+    /// Returns the size of the freed space.
+    /// ``MaraError::InvalidFree`` is returned if the space pointer of
+    /// ``alloc_data`` is not the start of a block, e.g. a pointer into the
+    /// middle of a block, and ``MaraError::DoubleFree`` if the block is
+    /// already free. The page is untouched in these cases.
+    pub fn delete_block(&mut self, alloc_data: &mut AllocationData) -> Result<usize, MaraError> {
+        self.check_canary();
         alloc_data.set_page(self);
         self.check_integrity();
+        // the bytes in front of an invalid pointer are user data
+        if !self.is_block_in_page(alloc_data.space.ptr()) {
+            return Err(MaraError::InvalidFree);
+        }
+        // user data can look like a pair of code blocks, only the real
+        // layout tells an interior pointer from the start of a block
+        if !self.is_block_start(alloc_data.space.ptr()) {
+            return Err(MaraError::InvalidFree);
+        }
         alloc_data.cache_code_blocks();
+        self.check_code_blocks_intact(alloc_data);
+        if !alloc_data.code_blocks_match() {
+            return Err(MaraError::InvalidFree);
+        }
+        // inserting a free space a second time would corrupt its bucket
+        if alloc_data.is_free() {
+            return Err(MaraError::DoubleFree);
+        }
+        // excluded blocks were never handed out
+        match self.pin_index(alloc_data.space.ptr()) {
+            Some(index) if self.pins[index].is_excluded() => return Err(MaraError::InvalidFree),
+            Some(index) => self.remove_pin(index),
+            None => {}
+        }
+        let size = alloc_data.space.size();
+        self.used_block_count -= 1;
+        self.used_bytes -= size;
         #[cfg(feature = "statistic")]
-        {
-            Statistic::freeDynamic(memory_block_size, first_byte);
+        self.bucket_list.record_touched(size);
+        if alloc_data.space.size() < self.coalesce_threshold {
+            // keep the block as it is so it can be reused for the same size
+            unsafe {
+                alloc_data.set_free(true);
+                self.bucket_list.insert(&mut alloc_data.space).expect(OFFSET_OVERFLOW);
+                self.poison(&alloc_data.space);
+            }
+            self.bucket_list.check_in_list(&alloc_data.space, true);
+        } else {
+            self.merge_with_neighbors(alloc_data);
         }
-        self.merge_with_neighbors(alloc_data);
         self.check_integrity();
+        Ok(size)
+    }
+    /// Pins the used block whose space starts at ``ptr``, so that ``compact``
+    /// and ``relocate`` do not move it. The pin is removed when the block is freed.
+    /// False if the page already holds ``MAX_PINNED_BLOCKS`` pinned blocks
+    /// and excluded ranges.
+    pub fn pin(&mut self, ptr: *mut u8) -> bool {
+        if self.is_pinned(ptr) {
+            return true;
+        }
+        if self.pin_count == MAX_PINNED_BLOCKS {
+            return false;
+        }
+        self.pins[self.pin_count] = Pin {
+            space: ptr,
+            ..Pin::UNUSED
+        };
+        self.pin_count += 1;
+        true
+    }
+    /// Removes the pin of the block whose space starts at ``ptr``.
+    /// False if the block is not pinned or excluded, excluded blocks stay pinned.
+    pub fn unpin(&mut self, ptr: *mut u8) -> bool {
+        match self.pin_index(ptr) {
+            Some(index) if !self.pins[index].is_excluded() => {
+                self.remove_pin(index);
+                true
+            }
+            _ => false,
+        }
+    }
+    /// Removes an entry of ``pins``, the order of the others is kept
+    fn remove_pin(&mut self, index: usize) {
+        self.pins.copy_within(index + 1..self.pin_count, index);
+        self.pin_count -= 1;
+    }
+    /// The index in ``pins`` of the block whose space starts at ``ptr``
+    fn pin_index(&self, ptr: *mut u8) -> Option<usize> {
+        self.pins[..self.pin_count]
+            .iter()
+            .position(|pin| pin.space == ptr)
+    }
+    /// True if the block whose space starts at ``ptr`` is never moved
+    pub fn is_pinned(&self, ptr: *mut u8) -> bool {
+        self.pin_index(ptr).is_some()
+    }
+    /// True if the block whose space starts at ``ptr`` was carved out by
+    /// ``exclude_range``
+    pub fn is_excluded(&self, ptr: *mut u8) -> bool {
+        self.pin_index(ptr)
+            .is_some_and(|index| self.pins[index].is_excluded())
+    }
+    /// Checks that the code block in front of ``ptr`` describes a block that
+    /// lies inside the dynamic sector of this page.
+    /// Only the bytes in front of ``ptr`` are read.
+    fn is_block_in_page(&self, ptr: *mut u8) -> bool {
+        let start_of_page = self.start_of_page as usize;
+        let end_of_page = self.end_of_page as usize;
+        let address = ptr as usize;
+        // room for the left code block in front of the space
+        if address <= start_of_page || address > end_of_page {
+            return false;
+        }
+        let (size, left_code_block) =
+            match unsafe { code_block::try_read_from_right(ptr.sub(1), self.start_of_page) } {
+                Ok(code_block) => code_block,
+                Err(_) => return false,
+            };
+        let code_block_size = address - left_code_block as usize;
+        // the space and the right code block end in the page
+        size >= SMALLEST_POSSIBLE_SPACE && size + code_block_size <= end_of_page + 1 - address
+    }
+    /// True if the space of a free or used block starts at ``ptr``.
+    /// The blocks are walked from the start of the page up to ``ptr``,
+    /// a code block that cannot be decoded ends the walk.
+    fn is_block_start(&self, ptr: *mut u8) -> bool {
+        let mut data_start = self.start_of_page as usize;
+        while data_start < ptr as usize {
+            let first_byte = data_start as *const u8;
+            let size = match unsafe { code_block::try_read_from_left(first_byte) } {
+                Ok(size) => size,
+                Err(_) => return false,
+            };
+            let code_block_size = unsafe { code_block::get_block_size(first_byte, false) };
+            if data_start + code_block_size == ptr as usize {
+                return true;
+            }
+            data_start += 2 * code_block_size + size;
+        }
+        false
+    }
+    /// Freed blocks with a space smaller than ``threshold`` bytes are not
+    /// merged with their free neighbors. 0 merges every block.
+    pub fn set_coalesce_threshold(&mut self, threshold: usize) {
+        self.coalesce_threshold = threshold;
+    }
+    /// Fills the free spaces with ``poison_byte`` instead of ``POISON_BYTE``.
+    /// The spaces that are already free are poisoned again.
+    #[cfg(feature = "poison")]
+    pub fn set_poison_byte(&mut self, poison_byte: u8) {
+        self.poison_byte = poison_byte;
+        for free_alloc in self.free_blocks() {
+            unsafe { self.poison(&free_alloc.space) };
+        }
+    }
+    /// The policies of the bucket list and the merges of this page
+    pub fn strategy(&self) -> AllocStrategy {
+        AllocStrategy {
+            fit: self.bucket_list.fit_policy(),
+            coalesce_threshold: self.coalesce_threshold,
+            insertion: self.bucket_list.insertion_policy(),
+        }
+    }
+    pub fn set_strategy(&mut self, strategy: AllocStrategy) {
+        self.bucket_list.set_fit_policy(strategy.fit);
+        self.bucket_list.set_insertion_policy(strategy.insertion);
+        self.coalesce_threshold = strategy.coalesce_threshold;
+    }
+    /// Grows the used block in place so that its space holds at least
+    /// ``new_size`` bytes. The block takes over its right neighbor if that is
+    /// free and large enough, the part that is not needed is split off again.
+    /// Returns false and leaves the block untouched if the neighbor is used,
+    /// too small or if the block is the last one in the page.
+    /// The space has to stay at its position, so the block cannot grow if it
+    /// would need larger code blocks.
+    pub fn grow_block(&mut self, alloc_data: &mut AllocationData, new_size: usize) -> bool {
+        unsafe {
+            alloc_data.set_page(self);
+            self.check_integrity();
+            alloc_data.cache_code_blocks();
+            self.check_code_blocks_intact(alloc_data);
+            let old_size = alloc_data.space.size();
+            if new_size <= old_size {
+                return true;
+            }
+            let code_block_size = alloc_data.code_block_size();
+            let mut right_alloc = match alloc_data.right_neighbor() {
+                Some(right_alloc) if right_alloc.is_free() => right_alloc,
+                _ => return false,
+            };
+            let merged_data_size =
+                alloc_data.calculate_data_size() + right_alloc.calculate_data_size();
+            if new_size + 2 * code_block_size > merged_data_size
+                || code_block::get_needed_code_block_size(new_size) != code_block_size
+            {
+                return false;
+            }
+            let remaining_data_size = merged_data_size - new_size - 2 * code_block_size;
+            // a remainder that is too small to be managed is added to the block
+            let space_size = if remaining_data_size < SMALLEST_POSSIBLE_FREE_SPACE {
+                if code_block::get_needed_code_block_size(new_size + remaining_data_size)
+                    != code_block_size
+                {
+                    return false;
+                }
+                new_size + remaining_data_size
+            } else {
+                new_size
+            };
+            right_alloc.space.cache_next(self.start_of_page);
+            self.bucket_list.remove(&right_alloc.space).expect(OFFSET_OVERFLOW);
+            self.check_alloc_end(&right_alloc);
+            alloc_data.space.set_size(space_size);
+            alloc_data.write_space_size_code_blocks(false);
+            if space_size == new_size {
+                let mut remaining = AllocationData::new();
+                remaining.set_page(self);
+                remaining.set_data_start(alloc_data.data_end().add(1));
+                remaining.set_data_end(right_alloc.data_end());
+                remaining.write_data_size_code_blocks(true);
+                self.bucket_list.insert(&mut remaining.space).expect(OFFSET_OVERFLOW);
+                self.repoison(&remaining.space, &right_alloc.space);
+                self.check_split_post(alloc_data, &remaining);
+            }
+            self.used_bytes += space_size - old_size;
+            alloc_data.check_neighbors();
+            self.check_integrity();
+            true
+        }
+    }
+    /// Shrinks the used block in place to a space of ``new_size`` bytes and
+    /// frees the tail, which is merged with a free right neighbor.
+    /// The space stays at its position, so the size is rounded up to the
+    /// smallest size that still needs the current code blocks.
+    /// Returns false and leaves the block untouched if the tail would be too
+    /// small to be managed as free space.
+    pub fn shrink_block(&mut self, alloc_data: &mut AllocationData, new_size: usize) -> bool {
+        unsafe {
+            alloc_data.set_page(self);
+            self.check_integrity();
+            alloc_data.cache_code_blocks();
+            self.check_code_blocks_intact(alloc_data);
+            let old_size = alloc_data.space.size();
+            let new_size = new_size.max(SMALLEST_POSSIBLE_SPACE).max(
+                code_block::smallest_size_for_code_block_size(alloc_data.code_block_size()),
+            );
+            if new_size >= old_size || old_size - new_size < SMALLEST_POSSIBLE_FREE_SPACE {
+                return false;
+            }
+            let data_end = alloc_data.data_end();
+            alloc_data.space.set_size(new_size);
+            alloc_data.write_space_size_code_blocks(false);
+            let mut tail = AllocationData::new();
+            tail.set_page(self);
+            tail.set_data_start(alloc_data.data_end().add(1));
+            tail.set_data_end(data_end);
+            self.used_bytes -= old_size - new_size;
+            self.merge_with_neighbors(&mut tail);
+            alloc_data.check_neighbors();
+            self.check_integrity();
+            true
+        }
     }
     /// Checks for both neighboring spaces if they are free.
     /// If so they are merged with the given allocation.
     #[inline]
     fn merge_with_neighbors(&mut self, alloc_data: &mut AllocationData) {
+        #[cfg(feature = "statistic")]
+        let stopwatch = crate::statistic::Stopwatch::start();
         unsafe {
             // merge with left if it is free space and self is not
             // at the start of the page
             if let Some(mut left_alloc) = alloc_data.left_neighbor() {
-                if code_block::is_free(left_alloc.data_start()) {
+                if left_alloc.is_free() {
                     left_alloc.check_consistency();
                     alloc_data.set_data_start(left_alloc.data_start());
                     left_alloc.space.cache_next(self.start_of_page);
-                    self.bucket_list.remove(&left_alloc.space);
+                    self.bucket_list.remove(&left_alloc.space).expect(OFFSET_OVERFLOW);
                     self.check_alloc_start(&left_alloc);
                     self.bucket_list.check_in_list(&left_alloc.space, false);
                 }
@@ -169,31 +910,162 @@ impl Page {
             // merge with right if it is free space and self is not
             // at the end of the page
             if let Some(mut right_alloc) = alloc_data.right_neighbor() {
-                if code_block::is_free(right_alloc.data_start()) {
+                if right_alloc.is_free() {
                     right_alloc.check_consistency();
                     alloc_data.set_data_end(right_alloc.data_end());
                     right_alloc.space.cache_next(self.start_of_page);
-                    self.bucket_list.remove(&right_alloc.space);
+                    self.bucket_list.remove(&right_alloc.space).expect(OFFSET_OVERFLOW);
                     self.check_alloc_end(&right_alloc);
                     self.bucket_list.check_in_list(&right_alloc.space, false);
                 }
             }
+            // the merged block must not reach into a physically adjacent page
+            self.check_alloc_start(alloc_data);
+            self.check_alloc_end(alloc_data);
             // write code blocks with set free flag
             // and get code block and space information for free
             alloc_data.write_data_size_code_blocks(true);
-            self.bucket_list.insert(&mut alloc_data.space);
+            self.check_merged_bucket(&alloc_data.space);
+            self.bucket_list.insert(&mut alloc_data.space).expect(OFFSET_OVERFLOW);
+            self.poison(&alloc_data.space);
             self.bucket_list.check_in_list(&alloc_data.space, true);
             alloc_data.check_neighbors();
         }
+        #[cfg(feature = "statistic")]
+        self.bucket_list.record_time(|timing| timing.merge += stopwatch.elapsed());
+    }
+    /// All blocks of the page, free and used, from left to right
+    pub fn blocks(&self) -> Blocks {
+        let mut first = AllocationData::new();
+        first.set_page(self as *const Self as *mut Self);
+        first.set_data_start(self.start_of_page as *mut u8);
+        first.cache_code_blocks();
+        Blocks { next: Some(first) }
+    }
+    /// All free blocks of the page in the order of the bucket list
+    pub fn free_blocks(&self) -> impl Iterator<Item = AllocationData> + '_ {
+        (0..BUCKET_LIST_SIZE)
+            .flat_map(move |index| self.bucket_list.iter_bucket(index))
+            .map(move |space| {
+                let mut block = AllocationData::new();
+                block.set_page(self as *const Self as *mut Self);
+                block.space.set_ptr(space.ptr());
+                block.cache_code_blocks();
+                block
+            })
+    }
+    /// The blocks left and right of the block whose space starts at ``ptr``
+    pub fn neighbor_blocks(&self, ptr: *mut u8) -> (Option<BlockInfo>, Option<BlockInfo>) {
+        if ptr as usize <= self.start_of_page as usize || ptr as usize > self.end_of_page as usize {
+            panic!("Mara: pointer is outside of the page");
+        }
+        let mut alloc_data = AllocationData::new();
+        alloc_data.set_page(self as *const Self as *mut Self);
+        alloc_data.space.set_ptr(ptr);
+        alloc_data.cache_code_blocks();
+        self.check_alloc(&alloc_data);
+        (
+            alloc_data.left_neighbor().as_ref().map(BlockInfo::from),
+            alloc_data.right_neighbor().as_ref().map(BlockInfo::from),
+        )
+    }
+    /// Checks the free lists of the page, see ``BucketList::verify_chains``
+    pub fn verify(&self) -> Result<(), MaraError> {
+        self.bucket_list.verify_chains()
+    }
+    /// The number of bytes that are occupied by code blocks in this page
+    pub fn code_block_overhead_bytes(&self) -> usize {
+        self.blocks().map(|block| 2 * block.code_block_size()).sum()
+    }
+    /// The bytes that were reserved for the user in front of the blocks
+    pub fn user_data(&mut self) -> &mut [u8] {
+        unsafe {
+            core::slice::from_raw_parts_mut(
+                self.start_of_page.sub(self.user_data_size) as *mut u8,
+                self.user_data_size,
+            )
+        }
+    }
+    /// The bytes of the free map for a page of ``page_size`` bytes,
+    /// 0 without ``free_map``
+    fn free_map_size(page_size: usize) -> usize {
+        match cfg!(feature = "free_map") {
+            true => page_size / (8 * SMALLEST_POSSIBLE_FREE_SPACE) + 1,
+            false => 0,
+        }
+    }
+    /// The byte and the bit of the free flag of the block that starts at
+    /// ``data_start``. Blocks are at least ``SMALLEST_POSSIBLE_FREE_SPACE``
+    /// bytes large, so no two blocks share a flag.
+    #[cfg(feature = "free_map")]
+    fn free_flag(&self, data_start: *const u8) -> (*mut u8, u8) {
+        let index =
+            (data_start as usize - self.start_of_page as usize) / SMALLEST_POSSIBLE_FREE_SPACE;
+        (unsafe { self.free_map.add(index / 8) }, 1 << (index % 8))
+    }
+    /// Reads the free flag of the block that starts at ``data_start``,
+    /// from its left code block or with ``free_map`` from the free map
+    #[inline]
+    pub fn is_free(&self, data_start: *const u8) -> bool {
+        #[cfg(not(feature = "free_map"))]
+        {
+            code_block::is_free(data_start)
+        }
+        #[cfg(feature = "free_map")]
+        {
+            let (byte, bit) = self.free_flag(data_start);
+            unsafe { *byte & bit != 0 }
+        }
+    }
+    /// Sets the free flag of the block that starts at ``data_start``
+    /// in the free map
+    #[cfg(feature = "free_map")]
+    #[inline]
+    pub fn set_free_in_map(&self, data_start: *const u8, free: bool) {
+        let (byte, bit) = self.free_flag(data_start);
+        unsafe {
+            if free {
+                *byte |= bit
+            } else {
+                *byte &= !bit
+            }
+        }
+    }
+    /// Counts a block that is handed out
+    #[inline]
+    fn record_used(&mut self, alloc_data: &AllocationData) {
+        self.used_block_count += 1;
+        self.used_bytes += alloc_data.space.size_from_memory();
+    }
+    /// The number of allocated blocks in this page
+    #[inline]
+    pub fn used_block_count(&self) -> usize {
+        self.used_block_count
+    }
+    /// The number of allocated bytes in this page, without code blocks.
+    /// Blocks might be larger than requested.
+    #[inline]
+    pub fn used_bytes(&self) -> usize {
+        self.used_bytes
     }
     #[inline]
     pub fn page_size(&self) -> usize {
         self.end_of_page as usize - self.start_of_page as usize + 1
     }
+    /// The largest space a single block in this page can have.
+    /// That is the page size without the code blocks of a block spanning
+    /// the whole page.
+    pub fn max_payload_size(&self) -> usize {
+        self.max_payload_size
+    }
+    /// the next page in the ring
+    #[inline]
+    pub fn next_page(&self) -> *mut Self {
+        self.next_page
+    }
     /// sets the next page
     #[inline]
     pub fn set_next_page(&mut self, next_page: *mut Self) {
-        if next_page != core::ptr::null_mut() {}
         self.next_page = next_page;
     }
     #[inline]
@@ -208,10 +1080,63 @@ impl Page {
     pub fn bucket_list(&self) -> &BucketList {
         &self.bucket_list
     }
+    #[inline]
+    pub fn bucket_list_mut(&mut self) -> &mut BucketList {
+        &mut self.bucket_list
+    }
 
     //////////////////////////////////////////////
     // Checks
 
+    /// Check that nothing was written into the page struct, before any of
+    /// its pointers is used
+    #[inline]
+    pub fn check_canary(&self) {
+        #[cfg(feature = "canary")]
+        {
+            if self.canary != PAGE_CANARY {
+                panic!(
+                    "Mara: the canary of the page at {:p} is overwritten, the page struct is corrupted",
+                    self
+                );
+            }
+        }
+    }
+    /// Check that nothing was written into the first ``size`` bytes of a
+    /// poisoned free space, before they are handed out
+    #[inline]
+    #[allow(unused_variables)]
+    fn check_poison(&self, space: &Space, size: usize) {
+        #[cfg(feature = "poison")]
+        {
+            let next_pointer_size = core::mem::size_of::<NextPointerType>();
+            for offset in next_pointer_size..size.min(space.size()) {
+                if unsafe { *space.ptr().add(offset) } != self.poison_byte {
+                    panic!(
+                        "Mara: the free block at {:p} was written at offset {} after it was freed",
+                        space.ptr(),
+                        offset
+                    );
+                }
+            }
+        }
+    }
+    /// Check that the merged space is inserted in the bucket of the size
+    /// its code blocks store
+    #[inline]
+    #[cfg_attr(not(feature = "consistency-checks"), allow(unused_variables))]
+    fn check_merged_bucket(&self, space: &Space) {
+        #[cfg(feature = "consistency-checks")]
+        {
+            let bucket = BucketList::lookup_bucket(space.size());
+            let stored_bucket = BucketList::lookup_bucket(space.size_from_memory());
+            if bucket != stored_bucket {
+                dbg!(space.size());
+                dbg!(space.size_from_memory());
+                panic!("merged space would be inserted in the wrong bucket");
+            }
+        }
+    }
     /// Check that page start is before its end
     #[inline]
     fn check_integrity(&self) {
@@ -226,6 +1151,7 @@ impl Page {
     }
     /// check that alloc pointers are in page boundaries
     #[inline]
+    #[cfg_attr(not(feature = "consistency-checks"), allow(unused_variables))]
     fn check_alloc(&self, alloc_data: &AllocationData) {
         #[cfg(feature = "consistency-checks")]
         {
@@ -236,6 +1162,7 @@ impl Page {
     }
     /// check that alloc.data_start is in page boundaries
     #[inline]
+    #[cfg_attr(not(feature = "consistency-checks"), allow(unused_variables))]
     pub fn check_alloc_start(&self, alloc_data: &AllocationData) {
         #[cfg(feature = "consistency-checks")]
         {
@@ -253,6 +1180,7 @@ impl Page {
     }
     /// check that alloc.data_end is in page boundaries
     #[inline]
+    #[cfg_attr(not(feature = "consistency-checks"), allow(unused_variables))]
     pub fn check_alloc_end(&self, alloc_data: &AllocationData) {
         #[cfg(feature = "consistency-checks")]
         {
@@ -270,13 +1198,15 @@ impl Page {
     }
     /// check that alloc.space pointer is in page boundaries
     #[inline]
+    #[cfg_attr(not(feature = "consistency-checks"), allow(unused_variables))]
     pub fn check_alloc_space(&self, alloc_data: &AllocationData) {
         #[cfg(feature = "consistency-checks")]
         {
             unsafe {
+                // the right code block follows the space, it ends at the page end at most
                 if !(alloc_data.space.ptr() as usize > self.start_of_page as usize
                     && (alloc_data.space.ptr().add(alloc_data.space.size()) as usize)
-                        < self.end_of_page as usize)
+                        <= self.end_of_page as usize)
                 {
                     dbg!(alloc_data.space.ptr());
                     dbg!(alloc_data.space.ptr().add(alloc_data.space.size()));
@@ -287,6 +1217,7 @@ impl Page {
     }
     // check preconditions of split
     #[inline]
+    #[cfg_attr(not(feature = "consistency-checks"), allow(unused_variables))]
     pub fn check_split_pre(&self, left_alloc: &AllocationData, free_space: &Space) {
         #[cfg(feature = "consistency-checks")]
         {
@@ -303,6 +1234,7 @@ impl Page {
     }
     // check boundaries and code blocks and cache after a successful split
     #[inline]
+    #[cfg_attr(not(feature = "consistency-checks"), allow(unused_variables))]
     pub fn check_split_post(&self, left_alloc: &AllocationData, right_alloc: &AllocationData) {
         #[cfg(feature = "consistency-checks")]
         {
@@ -317,8 +1249,9 @@ impl Page {
                 // let right_code_block_size = code_block::get_block_size(right_alloc.data_start());
                 self.check_alloc(left_alloc);
                 self.check_alloc(right_alloc);
+                // the right side has room for its next pointer
                 right_alloc.space.check_size(
-                    size_of::<NextPointerType>(),
+                    SMALLEST_POSSIBLE_SPACE,
                     self.page_size()
                         .sub(left_alloc.calculate_data_size())
                         .sub(2 * right_alloc.code_block_size()),
@@ -338,19 +1271,21 @@ impl Page {
                     panic!("split allocs are not direct neighbors")
                 }
                 // correct free bits
-                if code_block::is_free(left_alloc.data_start()) {
-                    dbg!(code_block::is_free(left_alloc.data_start()));
+                if left_alloc.is_free() {
+                    dbg!(left_alloc.is_free());
                     panic!("free bit not set after split");
                 }
-                if !code_block::is_free(right_alloc.data_start()) {
-                    dbg!(!code_block::is_free(right_alloc.data_start()));
+                if !right_alloc.is_free() {
+                    dbg!(!right_alloc.is_free());
                     panic!("free bit not set after split");
                 }
                 // correct free bits on the right side
+                #[cfg(not(feature = "free_map"))]
                 if code_block::is_free(left_alloc.calculate_right_code_block()) {
                     dbg!(code_block::is_free(left_alloc.calculate_right_code_block()));
                     panic!("free bit not set after split");
                 }
+                #[cfg(not(feature = "free_map"))]
                 if !code_block::is_free(right_alloc.calculate_right_code_block()) {
                     dbg!(!code_block::is_free(
                         right_alloc.calculate_right_code_block()
@@ -386,11 +1321,62 @@ impl Page {
             }
         }
     }
+    /// Both code blocks of a block are identical copies.
+    /// If they differ when the block is freed, the user wrote in front of
+    /// or behind the space of the block.
+    #[cfg_attr(not(feature = "consistency-checks"), allow(unused_variables))]
+    fn check_code_blocks_intact(&self, alloc_data: &AllocationData) {
+        #[cfg(feature = "consistency-checks")]
+        {
+            unsafe {
+                let code_block_size = alloc_data.code_block_size();
+                let left = alloc_data.data_start();
+                let right = alloc_data.data_end().add(1).sub(code_block_size);
+                if alloc_data.data_end() as usize > self.end_of_page as usize {
+                    dbg!(alloc_data.data_end());
+                    dbg!(self.end_of_page);
+                    panic!("code blocks of the freed block were overwritten");
+                }
+                for i in 0..code_block_size {
+                    if *left.add(i) != *right.add(i) {
+                        dbg!(left.add(i));
+                        dbg!(right.add(i));
+                        panic!("code blocks of the freed block were overwritten");
+                    }
+                }
+            }
+        }
+    }
+    /// The split free space is removed from the bucket list before the split
+    /// and only the remaining part is inserted again.
+    /// Check that the former successor of the split space is neither lost
+    /// nor duplicated in the process.
+    #[cfg_attr(not(feature = "consistency-checks"), allow(unused_variables))]
+    fn check_successor_preserved(&self, split_space: &Space) {
+        #[cfg(feature = "consistency-checks")]
+        {
+            if let Some(successor) = split_space.next() {
+                let bucket = BucketList::lookup_bucket(successor.size_from_memory());
+                let occurrences = self
+                    .bucket_list
+                    .iter_bucket(bucket)
+                    .filter(|space| space.ptr() == successor.ptr())
+                    .count();
+                if occurrences != 1 {
+                    dbg!(successor.ptr());
+                    dbg!(occurrences);
+                    panic!("successor of the split free space is lost or duplicated");
+                }
+            }
+        }
+    }
+    #[cfg_attr(not(feature = "consistency-checks"), allow(unused_variables))]
     fn check_dynamic_new_post(&self, alloc: &AllocationData) {
         #[cfg(feature = "consistency-checks")]
         {
             self.check_alloc_start(alloc);
             self.check_alloc_end(alloc);
+            alloc.space.check_cached_size();
             alloc.check_consistency();
             // check consistency of left neighbor
             if alloc.data_start() > self.start_of_page as *mut u8 {
@@ -407,3 +1393,209 @@ impl Page {
         }
     }
 }
+
+/// Computes the bounds of a used block whose space covers the ``len`` bytes at
+/// ``start`` in the free space of ``free_alloc``. Remainders that are too small
+/// to be managed as free space are added to the block. Growing the block this way
+/// might need larger code blocks, so the placement is repeated until the code
+/// block size is stable.
+/// Returns the first and last byte of the block and its code block size or None
+/// if there is no room for the code blocks.
+fn place_range(
+    free_alloc: &AllocationData,
+    start: *mut u8,
+    len: usize,
+) -> Option<(*mut u8, *mut u8, usize)> {
+    let free_start = free_alloc.data_start() as usize;
+    let free_end = free_alloc.data_end() as usize;
+    let start = start as usize;
+    let end = start + len - 1;
+    let mut code_block_size = code_block::get_needed_code_block_size(len);
+    loop {
+        if start < free_start + code_block_size || end + code_block_size > free_end {
+            return None;
+        }
+        let mut excluded_start = start - code_block_size;
+        if excluded_start - free_start < SMALLEST_POSSIBLE_FREE_SPACE {
+            excluded_start = free_start;
+        }
+        let mut excluded_end = end + code_block_size;
+        if free_end - excluded_end < SMALLEST_POSSIBLE_FREE_SPACE {
+            excluded_end = free_end;
+        }
+        let space_size = excluded_end - excluded_start + 1 - 2 * code_block_size;
+        if code_block::get_needed_code_block_size(space_size) <= code_block_size {
+            return Some((
+                excluded_start as *mut u8,
+                excluded_end as *mut u8,
+                code_block_size,
+            ));
+        }
+        code_block_size = code_block::get_needed_code_block_size(space_size);
+    }
+}
+
+/// Rounds ``address`` up to the next multiple of ``align``, which has to be a
+/// power of two
+fn align_up(address: usize, align: usize) -> usize {
+    (address + align - 1) & !(align - 1)
+}
+
+#[test]
+fn test_used_counts() {
+    use crate::page_list::PageList;
+    let mut memory = [0u8; 4096];
+    let mut page_list = PageList::new(memory.as_mut_ptr(), memory.len());
+    let page = page_list.get_page();
+    let counts = || unsafe { ((*page).used_block_count(), (*page).used_bytes()) };
+    let mut blocks = [core::ptr::null_mut(); 4];
+    for (i, block) in blocks.iter_mut().enumerate() {
+        let mut alloc_data = AllocationData::new();
+        alloc_data.space.set_size(10 * (i + 1));
+        let mut budget = usize::MAX;
+        page_list.dynamic_new(&mut alloc_data, &mut budget);
+        *block = alloc_data.space.ptr();
+    }
+    assert_eq!(counts(), (4, 100));
+    page_list.dynamic_delete(blocks[0]).unwrap();
+    page_list.dynamic_delete(blocks[2]).unwrap();
+    assert_eq!(counts(), (2, 60));
+    // merges with both neighbors
+    page_list.dynamic_delete(blocks[1]).unwrap();
+    assert_eq!(counts(), (1, 40));
+    page_list.dynamic_delete(blocks[3]).unwrap();
+    assert_eq!(counts(), (0, 0));
+}
+
+/// Allocates a block of ``size`` bytes between two used blocks and frees it
+/// again, so it is a free space that can not merge with its neighbors
+#[cfg(test)]
+fn enclosed_free_space(page_list: &mut crate::page_list::PageList, size: usize) -> *mut u8 {
+    let mut new = |size: usize| {
+        let mut alloc_data = AllocationData::new();
+        alloc_data.space.set_size(size);
+        let mut budget = usize::MAX;
+        page_list.dynamic_new(&mut alloc_data, &mut budget);
+        alloc_data.space.ptr()
+    };
+    new(16);
+    let hole = new(size);
+    new(16);
+    page_list.dynamic_delete(hole).unwrap();
+    hole
+}
+
+#[test]
+fn test_no_split_for_a_too_small_remainder() {
+    use crate::page_list::PageList;
+    const REQUESTED: usize = 20;
+    let mut memory = [0u8; 4096];
+    let mut page_list = PageList::new(memory.as_mut_ptr(), memory.len());
+    // one byte less than needed to keep the remainder as free space
+    let hole = enclosed_free_space(&mut page_list, REQUESTED + SMALLEST_POSSIBLE_FREE_SPACE - 1);
+    let block_count = page_list.blocks().count();
+    let mut alloc_data = AllocationData::new();
+    alloc_data.space.set_size(REQUESTED);
+    let mut budget = usize::MAX;
+    page_list.dynamic_new(&mut alloc_data, &mut budget);
+    assert_eq!(alloc_data.space.ptr(), hole);
+    // the whole hole is handed out and no remnant is created
+    assert_eq!(page_list.blocks().count(), block_count);
+    assert_eq!(
+        alloc_data.space.size_from_memory(),
+        REQUESTED + SMALLEST_POSSIBLE_FREE_SPACE - 1
+    );
+    assert!(!alloc_data.is_free());
+    assert!(alloc_data.code_blocks_match());
+    assert_eq!(page_list.verify(), Ok(()));
+}
+
+#[test]
+fn test_merge_with_both_neighbors_keeps_the_bucket() {
+    use crate::page_list::PageList;
+    // single byte code blocks in every configuration
+    const SIZE: usize = 50;
+    let mut memory = [0u8; 4096];
+    let mut page_list = PageList::new(memory.as_mut_ptr(), memory.len());
+    let page = page_list.get_page();
+    let mut blocks = [core::ptr::null_mut(); 4];
+    for block in blocks.iter_mut() {
+        let mut alloc_data = AllocationData::new();
+        alloc_data.space.set_size(SIZE);
+        let mut budget = usize::MAX;
+        page_list.dynamic_new(&mut alloc_data, &mut budget);
+        *block = alloc_data.space.ptr();
+    }
+    page_list.dynamic_delete(blocks[0]).unwrap();
+    page_list.dynamic_delete(blocks[2]).unwrap();
+    // merges with both neighbors, the merged size needs larger code blocks
+    page_list.dynamic_delete(blocks[1]).unwrap();
+    let data_start = unsafe { blocks[0].sub(1) };
+    let merged = page_list
+        .blocks()
+        .find(|block| block.data_start() == data_start)
+        .unwrap();
+    assert_eq!(merged.code_block_size(), 2);
+    let size = merged.space.size_from_memory();
+    assert_eq!(size, 3 * (SIZE + 2) - 4);
+    let bucket = BucketList::lookup_bucket(size);
+    assert!(unsafe { (*page).bucket_list() }
+        .iter_bucket(bucket)
+        .any(|space| space.ptr() == merged.space.ptr()));
+    assert_eq!(page_list.verify(), Ok(()));
+}
+
+#[test]
+fn test_hopeless_request_fails_without_search() {
+    use crate::page_list::PageList;
+    let mut memory = [0u8; 4096];
+    let mut page_list = PageList::new(memory.as_mut_ptr(), memory.len());
+    let page = page_list.get_page();
+    let largest = || unsafe { (*page).bucket_list().largest_free_size() };
+    assert_eq!(largest(), unsafe { (*page).max_payload_size() });
+    // use up the page and leave a single hole of 40 bytes
+    enclosed_free_space(&mut page_list, 40);
+    loop {
+        let mut alloc_data = AllocationData::new();
+        alloc_data.space.set_size(64);
+        let mut budget = usize::MAX;
+        page_list.dynamic_new(&mut alloc_data, &mut budget);
+        if !alloc_data.space.is_some() {
+            break;
+        }
+    }
+    assert!(largest() >= 40);
+    assert!(largest() < 64);
+    let mut alloc_data = AllocationData::new();
+    alloc_data.space.set_size(64);
+    let mut budget = 100;
+    page_list.dynamic_new(&mut alloc_data, &mut budget);
+    assert!(!alloc_data.space.is_some());
+    assert_eq!(budget, 100);
+    // the hole still fits
+    alloc_data.space.set_size(40);
+    page_list.dynamic_new(&mut alloc_data, &mut budget);
+    assert!(alloc_data.space.is_some());
+    assert_eq!(page_list.verify(), Ok(()));
+}
+
+#[test]
+fn test_blocks_cover_the_page() {
+    use crate::page_list::PageList;
+    let mut memory = [0u8; 4096];
+    let mut page_list = PageList::new(memory.as_mut_ptr(), memory.len());
+    let page = unsafe { &*page_list.get_page() };
+    let hole = enclosed_free_space(&mut page_list, 40);
+    let blocks: Vec<AllocationData> = page.blocks().collect();
+    // used, free hole, used and the free rest of the page
+    let free: Vec<bool> = blocks.iter().map(|block| block.is_free()).collect();
+    assert_eq!(free, [false, true, false, true]);
+    assert_eq!(blocks[1].space.ptr(), hole);
+    assert_eq!(blocks[1].space.size(), 40);
+    // the blocks are contiguous from the start to the end of the page
+    assert_eq!(blocks[0].data_start(), page.start_of_page() as *mut u8);
+    for pair in blocks.windows(2) {
+        assert_eq!(unsafe { pair[0].data_end().add(1) }, pair[1].data_start());
+    }
+    assert_eq!(blocks[3].data_end(), page.end_of_page() as *mut u8);
+}
@@ -1,62 +1,863 @@
+use crate::block_info::BlockInfo;
+use crate::bucket_list::{FitPolicy, InsertionPolicy};
 use crate::code_block;
+use crate::error::MaraError;
 use crate::globals::*;
 use crate::page::Page;
+use crate::strategy::AllocStrategy;
+#[cfg(feature = "statistic")]
+use crate::statistic::{SearchStats, Statistics, TimingBreakdown};
 use crate::AllocationData;
-use core::mem::size_of;
+use core::cmp::Ordering;
+use core::mem::{align_of, size_of};
+
+/// Which page is tried first for a new dynamic block
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum PagePolicy {
+    /// The pages are tried in ring order, starting with the first page
+    FirstPage,
+    /// The page with the largest share of used bytes is tried first.
+    /// Blocks are packed into few pages, so the others stay empty.
+    MostFull,
+    /// The page with the smallest share of used bytes is tried first.
+    /// Blocks are spread evenly over all pages.
+    LeastFull,
+}
 
 pub struct PageList {
     /// The first page in the ring that will be searched
     first_page: *mut Page,
-    /// Size of the data array
+    /// The page of the first segment, never changes.
+    /// Used to validate the ring independent of ``first_page``.
+    anchor_page: *mut Page,
+    /// Number of pages in the ring
+    page_count: usize,
+    /// Size of the data array of the largest page
     data_size: usize,
+    /// Counters of the dynamic sector. The search steps are kept by the
+    /// bucket lists.
+    #[cfg(feature = "statistic")]
+    statistics: Statistics,
+    /// Called with the pointer and the usable size of every new block
+    on_alloc: Option<fn(*mut u8, usize)>,
+    /// Called with the pointer and the usable size of every freed block
+    on_free: Option<fn(*mut u8, usize)>,
+    /// The order in which pages are tried for new dynamic blocks
+    page_policy: PagePolicy,
+}
+
+/// Walks the ring of pages once, starting with the first page
+pub struct Pages {
+    first: *mut Page,
+    next: Option<*mut Page>,
+}
+
+impl Iterator for Pages {
+    type Item = *mut Page;
+    fn next(&mut self) -> Option<*mut Page> {
+        let page = self.next.take()?;
+        unsafe { (*page).check_canary() };
+        let next = unsafe { (*page).next_page() };
+        if next != self.first {
+            self.next = Some(next);
+        }
+        Some(page)
+    }
 }
 
 impl PageList {
+    #[allow(dead_code)]
     pub fn new(data: *mut u8, data_size: usize) -> Self {
-        let max_code_block_size = code_block::get_needed_code_block_size(data_size);
-        if data_size > NextPointerType::max_value() as usize - 2 * max_code_block_size {
-            panic!(
-                "Pages greater than {} bytes are not supported",
-                NextPointerType::max_value() as usize - 2 * max_code_block_size
-            );
-        }
-        // store the location to the first page
-        let first_page = data as *mut Page;
-        // after that push the data start right to reserve page objects space
-        let data = unsafe { data.add(size_of::<Page>()) };
-        let data_size = data_size - size_of::<Page>();
-        unsafe { (*first_page).init(data, data_size) };
-        unsafe { (*first_page).set_next_page(first_page) };
-        Self {
-            first_page,
-            data_size: data_size,
+        Self::from_segments(&[(data, data_size)], 0)
+    }
+    /// Creates a page for every segment and links them to a ring in the
+    /// given order
+    /// #### user_data_size
+    /// bytes that are reserved in each page for the user, see ``Page::user_data``
+    pub fn from_segments(segments: &[(*mut u8, usize)], user_data_size: usize) -> Self {
+        Self::from_segment_iter(segments.iter().copied(), user_data_size)
+    }
+    /// Splits one region into pages of at most ``page_size`` bytes that
+    /// differ in size by at most one byte
+    pub fn with_page_size(data: *mut u8, data_size: usize, page_size: usize) -> Self {
+        let page_count = data_size.div_ceil(page_size);
+        let page_start = |index: usize| index * data_size / page_count;
+        let segments = (0..page_count).map(|index| {
+            let start = page_start(index);
+            (unsafe { data.add(start) }, page_start(index + 1) - start)
+        });
+        Self::from_segment_iter(segments, 0)
+    }
+    /// Like ``from_segments`` but the segments are generated on the fly,
+    /// so no buffer is needed for them
+    pub fn from_segment_iter(
+        segments: impl Iterator<Item = (*mut u8, usize)>,
+        user_data_size: usize,
+    ) -> Self {
+        let mut first_page: *mut Page = core::ptr::null_mut();
+        let mut last_page: *mut Page = core::ptr::null_mut();
+        let mut largest_data_size = 0;
+        let mut page_count = 0;
+        for (data, data_size) in segments {
+            page_count += 1;
+            let page = new_page(data, data_size, user_data_size);
+            if first_page.is_null() {
+                first_page = page;
+            } else {
+                unsafe { (*last_page).set_next_page(page) };
+            }
+            last_page = page;
+            largest_data_size = largest_data_size.max(unsafe { (*page).page_size() });
+        }
+        if first_page.is_null() {
+            panic!("Mara: at least one segment is needed");
         }
+        unsafe { (*last_page).set_next_page(first_page) };
+        let page_list = Self {
+            first_page,
+            anchor_page: first_page,
+            page_count,
+            data_size: largest_data_size,
+            #[cfg(feature = "statistic")]
+            statistics: Statistics::default(),
+            on_alloc: None,
+            on_free: None,
+            page_policy: PagePolicy::FirstPage,
+        };
+        page_list.check_page_alignment();
+        page_list
     }
+    #[allow(dead_code)]
     pub fn get_page(&self) -> *const Page {
         self.first_page
     }
+    /// All pages of the ring, starting with the first page
+    pub fn pages(&self) -> Pages {
+        Pages {
+            first: self.first_page,
+            next: Some(self.first_page),
+        }
+    }
+    /// All pages of the ring in the order of the page policy.
+    /// Pages with the same share of used bytes are ordered by address.
+    fn pages_by_policy(&self) -> impl Iterator<Item = *mut Page> + '_ {
+        let mut ring = self.pages();
+        let mut previous = None;
+        core::iter::from_fn(move || {
+            let page = match self.page_policy {
+                PagePolicy::FirstPage => ring.next(),
+                PagePolicy::MostFull | PagePolicy::LeastFull => self
+                    .pages()
+                    .filter(|&page| {
+                        previous.is_none_or(|previous| {
+                            self.page_order(previous, page) == Ordering::Less
+                        })
+                    })
+                    .min_by(|&a, &b| self.page_order(a, b)),
+            };
+            previous = page;
+            page
+        })
+    }
+    /// Compares the share of used bytes of two pages, the page that is tried
+    /// first is the lesser one
+    fn page_order(&self, a: *mut Page, b: *mut Page) -> Ordering {
+        let (a_used, a_size) = unsafe { ((*a).used_bytes() as u128, (*a).page_size() as u128) };
+        let (b_used, b_size) = unsafe { ((*b).used_bytes() as u128, (*b).page_size() as u128) };
+        let fullness = (a_used * b_size).cmp(&(b_used * a_size));
+        let fullness = match self.page_policy {
+            PagePolicy::MostFull => fullness.reverse(),
+            _ => fullness,
+        };
+        fullness.then((a as usize).cmp(&(b as usize)))
+    }
+    /// The number of pages in the ring.
+    /// The ring is walked from the first page, but at most as many pages as
+    /// were created. A ring that is cut short shows up as a smaller count,
+    /// other broken links are only found by ``verify``.
+    pub fn page_count(&self) -> usize {
+        let mut page = self.first_page;
+        for count in 1..self.page_count {
+            page = unsafe { (*page).next_page() };
+            if page.is_null() || page == self.first_page {
+                return count;
+            }
+        }
+        self.page_count
+    }
+    /// The page with the given index in the order of the segments
+    pub fn page(&self, index: usize) -> *mut Page {
+        self.pages()
+            .nth(index)
+            .expect("Mara: there is no page with this index")
+    }
+    /// The page whose data contains ``ptr``
+    fn page_of(&self, ptr: *const u8) -> *mut Page {
+        self.find_page(ptr).expect("Mara: pointer is not in any page")
+    }
+    /// Like ``page_of`` but None if ``ptr`` is not in any page
+    fn find_page(&self, ptr: *const u8) -> Option<*mut Page> {
+        self.pages().find(|&page| unsafe {
+            (*page).start_of_page() as usize <= ptr as usize
+                && ptr as usize <= (*page).end_of_page() as usize
+        })
+    }
+    /// The used block whose space starts at ``ptr``.
+    /// None if ``ptr`` is not the space of a used block in any page.
+    pub fn allocation_data(&self, ptr: *mut u8) -> Option<AllocationData> {
+        let page = self.find_page(ptr)?;
+        if unsafe { (*page).is_excluded(ptr) } {
+            return None;
+        }
+        unsafe { (*page).blocks() }
+            .find(|block| block.space.ptr() == ptr && !block.is_free())
+    }
+    /// Takes a static block from the first page that has room at its end,
+    /// see ``Page::get_static_block``.
+    /// The pages are fixed, so None is returned if no page has room.
+    pub fn static_new(&mut self, size_in_byte: usize) -> Option<*mut u8> {
+        self.check_heap("before", format_args!("static_new({})", size_in_byte));
+        let block = self
+            .pages()
+            .find_map(|page| unsafe { (*page).get_static_block(size_in_byte) });
+        self.check_heap("after", format_args!("static_new({})", size_in_byte));
+        block
+    }
     /// #### size_in_byte
-    /// size of the block
+    /// size of the block, smaller sizes are rounded up to ``SMALLEST_POSSIBLE_SPACE``
+    /// #### budget
+    /// the maximum number of search and split steps
     /// #### return
     /// a pointer to the block
-    pub fn dynamic_new(&mut self, alloc_data: &mut AllocationData) {
+    pub fn dynamic_new(&mut self, alloc_data: &mut AllocationData, budget: &mut usize) {
+        alloc_data.space.check_size(1, self.data_size);
+        let size = alloc_data.space.size();
+        self.check_heap("before", format_args!("dynamic_new({})", size));
+        #[cfg(feature = "statistic")]
+        let requested = alloc_data.space.size();
+        // a freed space has to be large enough for its next pointer
+        if alloc_data.space.size() < SMALLEST_POSSIBLE_SPACE {
+            alloc_data.space.set_size(SMALLEST_POSSIBLE_SPACE);
+        }
+        for page in self.pages_by_policy() {
+            // the block does not fit in a smaller page
+            if alloc_data.space.size() > unsafe { (*page).max_payload_size() } {
+                continue;
+            }
+            unsafe { (*page).get_dynamic_block(alloc_data, budget) };
+            if alloc_data.space.is_some() || *budget == 0 {
+                break;
+            }
+        }
+        #[cfg(feature = "statistic")]
+        {
+            if alloc_data.space.is_some() {
+                // the block might be larger than requested
+                self.statistics.record_new(requested, alloc_data.space.size());
+            }
+        }
+        self.notify_alloc(alloc_data);
+        self.check_heap("after", format_args!("dynamic_new({})", size));
+    }
+    /// #### size_in_byte
+    /// size of the block, smaller sizes are rounded up to ``SMALLEST_POSSIBLE_SPACE``
+    /// #### align
+    /// alignment of the space, a power of two
+    pub fn aligned_new(&mut self, alloc_data: &mut AllocationData, align: usize) {
+        if !align.is_power_of_two() {
+            panic!("Mara: alignment {} is not a power of two", align);
+        }
+        if align > MAX_ALIGN {
+            return;
+        }
         alloc_data.space.check_size(1, self.data_size);
-        unsafe { (*self.first_page).get_dynamic_block(alloc_data) };
+        let size = alloc_data.space.size();
+        self.check_heap("before", format_args!("aligned_new({}, {})", size, align));
+        #[cfg(feature = "statistic")]
+        let requested = alloc_data.space.size();
+        if alloc_data.space.size() < SMALLEST_POSSIBLE_SPACE {
+            alloc_data.space.set_size(SMALLEST_POSSIBLE_SPACE);
+        }
+        for page in self.pages_by_policy() {
+            if alloc_data.space.size() > unsafe { (*page).max_payload_size() } {
+                continue;
+            }
+            unsafe { (*page).get_aligned_block(alloc_data, align) };
+            if alloc_data.space.is_some() {
+                break;
+            }
+        }
         #[cfg(feature = "statistic")]
         {
-            byte * hurr = nullptr;
-            Statistic::newDynamic(
-                codeblock::read_from_right((start_of_space - 1), hurr),
-                start_of_space,
-            );
+            if alloc_data.space.is_some() {
+                let size = alloc_data.space.size_from_memory();
+                self.statistics.record_new(requested, size);
+            }
+        }
+        self.notify_alloc(alloc_data);
+        self.check_heap("after", format_args!("aligned_new({}, {})", size, align));
+    }
+    /// Takes the first free space of a bucket without splitting it
+    /// #### index
+    /// the index of the bucket, has to be smaller than ``BUCKET_LIST_SIZE``
+    pub fn bucket_new(&mut self, alloc_data: &mut AllocationData, index: usize) {
+        if index >= BUCKET_LIST_SIZE {
+            panic!("Mara: there are only {} buckets", BUCKET_LIST_SIZE);
+        }
+        for page in self.pages() {
+            unsafe { (*page).pop_bucket(alloc_data, index) };
+            if alloc_data.space.is_some() {
+                break;
+            }
+        }
+        #[cfg(feature = "statistic")]
+        {
+            if alloc_data.space.is_some() {
+                let size = alloc_data.space.size_from_memory();
+                self.statistics.record_new(size, size);
+            }
+        }
+        self.notify_alloc(alloc_data);
+    }
+    /// Moves the block at ``address`` to the free space with the lowest address
+    /// in front of it in the same page and frees the old block.
+    /// #### return
+    /// the new location or None if there is no lower free space that fits
+    /// or the block is pinned
+    pub fn relocate(&mut self, address: *mut u8) -> Option<*mut u8> {
+        if unsafe { (*self.page_of(address)).is_pinned(address) } {
+            return None;
+        }
+        let size = self.usable_size(address);
+        let mut alloc_data = AllocationData::new();
+        alloc_data.space.set_size(size);
+        unsafe { (*self.page_of(address)).get_lower_block(&mut alloc_data, address) };
+        if !alloc_data.space.is_some() {
+            return None;
+        }
+        let new_address = alloc_data.space.ptr();
+        unsafe { core::ptr::copy_nonoverlapping(address, new_address, size) };
+        #[cfg(feature = "statistic")]
+        self.statistics.record_new(size, alloc_data.space.size_from_memory());
+        self.notify_alloc(&alloc_data);
+        self.dynamic_delete(address).expect("Mara: pointer is not in any page");
+        Some(new_address)
+    }
+    /// Grows the block at ``address`` in place, see ``Page::grow_block``
+    pub fn grow_in_place(&mut self, address: *mut u8, new_size: usize) -> bool {
+        self.check_heap("before", format_args!("grow_in_place({:p}, {})", address, new_size));
+        let mut alloc_data = AllocationData::new();
+        alloc_data.space.set_ptr(address);
+        let old_size = alloc_data.space.size_from_memory();
+        let grown = unsafe { (*self.page_of(address)).grow_block(&mut alloc_data, new_size) };
+        #[cfg(feature = "statistic")]
+        self.statistics.record_resize(old_size, alloc_data.space.size_from_memory());
+        self.notify_resize(address, old_size, alloc_data.space.size_from_memory());
+        self.check_heap("after", format_args!("grow_in_place({:p}, {})", address, new_size));
+        grown
+    }
+    /// Shrinks the block at ``address`` in place, see ``Page::shrink_block``
+    pub fn shrink_in_place(&mut self, address: *mut u8, new_size: usize) -> bool {
+        self.check_heap(
+            "before",
+            format_args!("shrink_in_place({:p}, {})", address, new_size),
+        );
+        let mut alloc_data = AllocationData::new();
+        alloc_data.space.set_ptr(address);
+        let old_size = alloc_data.space.size_from_memory();
+        let shrunk = unsafe { (*self.page_of(address)).shrink_block(&mut alloc_data, new_size) };
+        #[cfg(feature = "statistic")]
+        self.statistics
+            .record_resize(old_size, alloc_data.space.size_from_memory());
+        self.notify_resize(address, old_size, alloc_data.space.size_from_memory());
+        self.check_heap(
+            "after",
+            format_args!("shrink_in_place({:p}, {})", address, new_size),
+        );
+        shrunk
+    }
+    /// Shrinks or grows the block at ``address`` in place if possible,
+    /// otherwise moves it to a new block that is large enough.
+    /// None if neither worked, the block is untouched in that case.
+    /// #### align
+    /// alignment of a moved block, a block that stays in place keeps its address
+    pub fn resize(&mut self, address: *mut u8, new_size: usize, align: usize) -> Option<*mut u8> {
+        if new_size <= self.usable_size(address) {
+            self.shrink_in_place(address, new_size);
+            return Some(address);
+        }
+        if self.grow_in_place(address, new_size) {
+            return Some(address);
+        }
+        let mut alloc_data = AllocationData::new();
+        alloc_data.space.set_size(new_size);
+        if align > 1 {
+            self.aligned_new(&mut alloc_data, align);
+        } else {
+            let mut budget = usize::MAX;
+            self.dynamic_new(&mut alloc_data, &mut budget);
+        }
+        if !alloc_data.space.is_some() {
+            return None;
+        }
+        let new_address = alloc_data.space.ptr();
+        let old_size = self.usable_size(address);
+        unsafe { core::ptr::copy_nonoverlapping(address, new_address, old_size.min(new_size)) };
+        self.dynamic_delete(address).expect("Mara: pointer is not in any page");
+        Some(new_address)
+    }
+    /// Moves the used blocks of every page to its start, see ``Page::compact``
+    pub fn compact(&mut self, mut update: impl FnMut(*mut u8, *mut u8)) {
+        self.check_heap("before", format_args!("compact"));
+        for page in self.pages() {
+            unsafe { (*page).compact(&mut update) };
+        }
+        self.check_heap("after", format_args!("compact"));
+    }
+    /// Selects where freed spaces are added to their bucket in all pages
+    pub fn set_insertion_policy(&mut self, insertion_policy: InsertionPolicy) {
+        for page in self.pages() {
+            unsafe { (*page).bucket_list_mut().set_insertion_policy(insertion_policy) };
+        }
+    }
+    /// Selects the order in which pages are tried for new dynamic blocks
+    pub fn set_page_policy(&mut self, page_policy: PagePolicy) {
+        self.page_policy = page_policy;
+    }
+    pub fn page_policy(&self) -> PagePolicy {
+        self.page_policy
+    }
+    /// Frees all dynamic blocks of all pages at once, see ``Page::reset``
+    pub fn reset(&mut self) {
+        for page in self.pages() {
+            unsafe { (*page).reset() };
+        }
+        #[cfg(feature = "statistic")]
+        {
+            self.statistics.allocated_bytes = 0;
+            self.statistics.live_blocks = 0;
+        }
+        self.check_heap("after", format_args!("reset()"));
+    }
+    pub fn set_fit_policy(&mut self, fit_policy: FitPolicy) {
+        for page in self.pages() {
+            unsafe { (*page).bucket_list_mut().set_fit_policy(fit_policy) };
+        }
+    }
+    /// The strategy of the first page, all pages share it
+    pub fn strategy(&self) -> AllocStrategy {
+        unsafe { (*self.first_page).strategy() }
+    }
+    /// Sets the strategy of all pages
+    pub fn set_strategy(&mut self, strategy: AllocStrategy) {
+        for page in self.pages() {
+            unsafe { (*page).set_strategy(strategy) };
+        }
+    }
+    /// Sets the size below which freed blocks are not merged in all pages
+    pub fn set_coalesce_threshold(&mut self, threshold: usize) {
+        for page in self.pages() {
+            unsafe { (*page).set_coalesce_threshold(threshold) };
+        }
+    }
+    /// Sets the byte that fills the free spaces in all pages
+    #[cfg(feature = "poison")]
+    pub fn set_poison_byte(&mut self, poison_byte: u8) {
+        for page in self.pages() {
+            unsafe { (*page).set_poison_byte(poison_byte) };
+        }
+    }
+    /// Permanently removes a range of the data array from the managed memory
+    /// #### start
+    /// first byte of the range
+    /// #### len
+    /// length of the range in bytes
+    pub fn exclude_range(&mut self, start: *mut u8, len: usize) {
+        unsafe { (*self.page_of(start)).exclude_range(start, len) };
+    }
+    /// Pins the used block at ``address``, see ``Page::pin``.
+    /// False if ``address`` is not the space of a used block.
+    pub fn pin(&mut self, address: *mut u8) -> bool {
+        match self.allocation_data(address) {
+            Some(block) => unsafe { (*block.page()).pin(address) },
+            None => false,
+        }
+    }
+    /// Removes the pin of the block at ``address``, see ``Page::unpin``
+    pub fn unpin(&mut self, address: *mut u8) -> bool {
+        match self.find_page(address) {
+            Some(page) => unsafe { (*page).unpin(address) },
+            None => false,
+        }
+    }
+    /// All blocks of all pages in physical order
+    pub fn blocks(&self) -> impl Iterator<Item = AllocationData> + '_ {
+        self.pages().flat_map(|page| unsafe { (*page).blocks() })
+    }
+    /// All free blocks of all pages in bucket list order
+    pub fn free_blocks(&self) -> impl Iterator<Item = AllocationData> + '_ {
+        self.pages().flat_map(|page| unsafe { (*page).free_blocks() })
+    }
+    /// The size of the space of the block at ``ptr``
+    pub fn usable_size(&self, ptr: *mut u8) -> usize {
+        let page = self.page_of(ptr);
+        let mut alloc_data = AllocationData::new();
+        alloc_data.space.set_ptr(ptr);
+        alloc_data.set_page(page);
+        alloc_data.cache_code_blocks();
+        unsafe { (*page).check_alloc_start(&alloc_data) };
+        alloc_data.space.size()
+    }
+    /// The size of the largest free space in all pages, 0 if nothing is free
+    pub fn largest_free_block(&self) -> usize {
+        self.free_blocks()
+            .map(|block| block.space.size())
+            .max()
+            .unwrap_or(0)
+    }
+    /// The sum of the spaces of all free blocks in all pages
+    pub fn total_free_bytes(&self) -> usize {
+        self.free_blocks().map(|block| block.space.size()).sum()
+    }
+    /// The bytes of all dynamic sectors including the code blocks
+    pub fn dynamic_capacity(&self) -> usize {
+        self.pages().map(|page| unsafe { (*page).page_size() }).sum()
+    }
+    /// The largest block that fits in any page if it is empty
+    pub fn max_allocation_size(&self) -> usize {
+        self.pages()
+            .map(|page| unsafe { (*page).max_payload_size() })
+            .max()
+            .unwrap_or(0)
+    }
+    /// The neighbors of the block at ``ptr`` in its page
+    pub fn neighbor_blocks(&self, ptr: *mut u8) -> (Option<BlockInfo>, Option<BlockInfo>) {
+        unsafe { (*self.page_of(ptr)).neighbor_blocks(ptr) }
+    }
+    /// Checks the page ring and the free lists of all pages
+    pub fn verify(&self) -> Result<(), MaraError> {
+        self.verify_ring()?;
+        self.pages().try_for_each(|page| unsafe { (*page).verify() })
+    }
+    /// Checks that walking the next pages from the first segment returns to
+    /// it after exactly ``page_count`` pages and passes ``first_page``
+    fn verify_ring(&self) -> Result<(), MaraError> {
+        let mut page = self.anchor_page;
+        let mut first_page_found = false;
+        for step in 0..self.page_count {
+            if page.is_null() || (step != 0 && page == self.anchor_page) {
+                return Err(MaraError::BrokenPageRing);
+            }
+            first_page_found |= page == self.first_page;
+            page = unsafe { (*page).next_page() };
+        }
+        if page != self.anchor_page || !first_page_found {
+            return Err(MaraError::BrokenPageRing);
+        }
+        Ok(())
+    }
+    /// The number of bytes that are occupied by code blocks in all pages
+    pub fn code_block_overhead_bytes(&self) -> usize {
+        self.pages()
+            .map(|page| unsafe { (*page).code_block_overhead_bytes() })
+            .sum()
+    }
+    /// The search statistics of all pages
+    #[cfg(feature = "statistic")]
+    pub fn search_stats(&self) -> SearchStats {
+        let mut search_stats = SearchStats::default();
+        for page in self.pages() {
+            search_stats.accumulate(unsafe { (*page).bucket_list().search_stats() });
+        }
+        search_stats
+    }
+    /// The usage counters together with the search statistics
+    #[cfg(feature = "statistic")]
+    pub fn statistics(&self) -> Statistics {
+        let mut statistics = self.statistics;
+        statistics.search = self.search_stats();
+        statistics
+    }
+    /// The time spent in merges, bucket updates and searches of all pages
+    #[cfg(feature = "statistic")]
+    pub fn timing_breakdown(&self) -> TimingBreakdown {
+        let mut timing = TimingBreakdown::default();
+        for page in self.pages() {
+            timing.accumulate(unsafe { (*page).bucket_list().timing() });
+        }
+        timing
+    }
+    /// Clears the cumulative counters, see ``Statistics::reset``
+    #[cfg(feature = "statistic")]
+    pub fn reset_statistics(&mut self) {
+        self.statistics.reset();
+        for page in self.pages() {
+            let bucket_list = unsafe { (*page).bucket_list() };
+            let mut search_stats = bucket_list.search_stats();
+            search_stats.reset();
+            bucket_list.set_search_stats(search_stats);
+            bucket_list.record_time(|timing| *timing = TimingBreakdown::default());
+        }
+    }
+    /// Sets the peaks to the current state, see ``Statistics::reset_peaks``
+    #[cfg(feature = "statistic")]
+    pub fn reset_peaks(&mut self) {
+        self.statistics.reset_peaks();
+        for page in self.pages() {
+            let bucket_list = unsafe { (*page).bucket_list_mut() };
+            let mut search_stats = bucket_list.search_stats();
+            search_stats.reset_peak();
+            bucket_list.set_search_stats(search_stats);
+            bucket_list.reset_max_chain_lengths();
+        }
+    }
+    /// The longest free list each bucket had in any page
+    #[cfg(feature = "statistic")]
+    pub fn max_chain_lengths(&self) -> [usize; BUCKET_LIST_SIZE] {
+        let mut max_chain_lengths = [0; BUCKET_LIST_SIZE];
+        for page in self.pages() {
+            let page_lengths = unsafe { (*page).bucket_list().max_chain_lengths() };
+            for (max, length) in max_chain_lengths.iter_mut().zip(page_lengths.iter()) {
+                *max = (*max).max(*length);
+            }
+        }
+        max_chain_lengths
+    }
+    /// The buckets that were touched in any page
+    #[cfg(feature = "statistic")]
+    pub fn touched_buckets(&self) -> u64 {
+        self.pages()
+            .fold(0, |touched, page| touched | unsafe { (*page).bucket_list().touched_buckets() })
+    }
+    /// See ``Mara::on_alloc``
+    pub fn set_on_alloc(&mut self, callback: Option<fn(*mut u8, usize)>) {
+        self.on_alloc = callback;
+    }
+    /// See ``Mara::on_free``
+    pub fn set_on_free(&mut self, callback: Option<fn(*mut u8, usize)>) {
+        self.on_free = callback;
+    }
+    /// Reports a new block to the ``on_alloc`` callback,
+    /// nothing happens if the allocation failed
+    fn notify_alloc(&self, alloc_data: &AllocationData) {
+        if let Some(on_alloc) = self.on_alloc {
+            if alloc_data.space.is_some() {
+                on_alloc(alloc_data.space.ptr(), alloc_data.space.size_from_memory());
+            }
+        }
+    }
+    /// Reports a block that was resized in place as a free of the old size
+    /// and an allocation of the new size, so the callbacks see the same
+    /// size on allocation and on free
+    fn notify_resize(&self, address: *mut u8, old_size: usize, new_size: usize) {
+        if old_size == new_size {
+            return;
+        }
+        if let Some(on_free) = self.on_free {
+            on_free(address, old_size);
+        }
+        if let Some(on_alloc) = self.on_alloc {
+            on_alloc(address, new_size);
         }
     }
     /// frees a dynamic block
     /// #### address
     /// a pointer to the block
-    pub fn dynamic_delete(&mut self, address: *mut u8) {
+    /// #### return
+    /// ``MaraError::InvalidFree`` if ``address`` is not the start of a block
+    /// in any page or ``MaraError::DoubleFree`` if the block is already free,
+    /// nothing is changed in these cases
+    pub fn dynamic_delete(&mut self, address: *mut u8) -> Result<(), MaraError> {
+        let page = self.find_page(address).ok_or(MaraError::InvalidFree)?;
+        self.check_heap("before", format_args!("dynamic_delete({:p})", address));
         let mut alloc_data = AllocationData::new();
         alloc_data.space.set_ptr(address);
-        unsafe { (*self.first_page).delete_block(&mut alloc_data) };
+        let size = unsafe { (*page).delete_block(&mut alloc_data)? };
+        #[cfg(feature = "statistic")]
+        self.statistics.record_delete(size);
+        if let Some(on_free) = self.on_free {
+            on_free(address, size);
+        }
+        self.check_heap("after", format_args!("dynamic_delete({:p})", address));
+        Ok(())
+    }
+
+    //////////////////////////////////////////////
+    // Checks
+
+    /// verify the whole heap so that a corruption is reported by the
+    /// operation that caused it
+    #[allow(unused_variables)]
+    fn check_heap(&self, when: &str, operation: core::fmt::Arguments) {
+        #[cfg(feature = "paranoid")]
+        {
+            if let Err(error) = self.verify() {
+                panic!("Mara: heap is corrupted {} {}: {}", when, operation, error);
+            }
+        }
+    }
+
+    /// check that the page list was created with ``new`` and not zeroed
+    #[inline]
+    pub fn check_initialized(&self) {
+        #[cfg(feature = "consistency-checks")]
+        {
+            if self.first_page.is_null() {
+                panic!("Mara used before initialization");
+            }
+        }
+    }
+    /// report the blocks that are still in use when the page list goes away.
+    /// Skipped while panicking and for a page list that was never initialized.
+    /// Excluded ranges are not counted.
+    pub fn check_leaks(&self) {
+        #[cfg(all(feature = "consistency-checks", not(feature = "no_std")))]
+        {
+            if self.first_page.is_null() || std::thread::panicking() {
+                return;
+            }
+            let leaked = self.leaked_blocks();
+            if leaked != 0 {
+                eprintln!("Mara: {} blocks were not freed", leaked);
+            }
+        }
     }
+    /// The number of used blocks that are not excluded ranges
+    #[cfg(any(test, all(feature = "consistency-checks", not(feature = "no_std"))))]
+    fn leaked_blocks(&self) -> usize {
+        self.blocks()
+            .filter(|block| {
+                !block.is_free() && unsafe { !(*block.page()).is_excluded(block.space.ptr()) }
+            })
+            .count()
+    }
+    /// check that every page struct in the ring is aligned
+    fn check_page_alignment(&self) {
+        #[cfg(feature = "consistency-checks")]
+        {
+            let mut page = self.first_page;
+            loop {
+                if page as usize & (align_of::<Page>() - 1) != 0 {
+                    dbg!(page);
+                    dbg!(align_of::<Page>());
+                    panic!("page struct is not aligned");
+                }
+                page = unsafe { (*page).next_page() };
+                if page == self.first_page {
+                    break;
+                }
+            }
+        }
+    }
+}
+
+/// The smallest segment that holds a page struct in any alignment,
+/// ``user_data_size`` bytes of user data and a free block
+pub fn min_page_size(user_data_size: usize) -> usize {
+    size_of::<Page>() + align_of::<Page>() + user_data_size + 2 * SMALLEST_POSSIBLE_FREE_SPACE
+}
+
+/// Places a page struct at the start of a segment and initializes the page
+/// with the memory behind it
+fn new_page(data: *mut u8, data_size: usize, user_data_size: usize) -> *mut Page {
+    if (data as usize..data as usize + data_size).contains(&ZERO_SIZE_BLOCK) {
+        panic!(
+            "Mara: a segment must not contain the address {:#x}",
+            ZERO_SIZE_BLOCK
+        );
+    }
+    let min_size = min_page_size(user_data_size);
+    if data_size < min_size {
+        panic!(
+            "Mara: a page needs at least {} bytes, {} bytes are too small",
+            min_size, data_size
+        );
+    }
+    let max_code_block_size = code_block::get_needed_code_block_size(data_size);
+    if data_size > NextPointerType::MAX as usize - 2 * max_code_block_size {
+        panic!(
+            "Pages greater than {} bytes are not supported",
+            NextPointerType::MAX as usize - 2 * max_code_block_size
+        );
+    }
+    // store the location to the page
+    let (page, padding) = align_page(data);
+    // after that push the data start right to reserve page objects space
+    let data = unsafe { data.add(padding + size_of::<Page>()) };
+    let data_size = data_size - padding - size_of::<Page>();
+    unsafe { (*page).init(data, data_size, user_data_size) };
+    page
+}
+
+/// Rounds ``memory`` up to the next location where a page struct can be placed.
+/// Returns the page location and the number of bytes that were skipped.
+fn align_page(memory: *mut u8) -> (*mut Page, usize) {
+    let padding = memory.align_offset(align_of::<Page>());
+    (unsafe { memory.add(padding) } as *mut Page, padding)
+}
+
+#[test]
+fn test_page_alignment() {
+    let mut memory = [0u8; 4096];
+    for offset in 0..2 * align_of::<Page>() {
+        let data = unsafe { memory.as_mut_ptr().add(offset) };
+        let page_list = PageList::new(data, memory.len() - offset);
+        assert_eq!(page_list.get_page() as usize % align_of::<Page>(), 0);
+        assert!(page_list.get_page() as usize >= data as usize);
+        page_list.check_page_alignment();
+    }
+}
+
+#[test]
+fn test_verify_ring() {
+    let mut memory = [0u8; 3 * 4096];
+    let (first, rest) = memory.split_at_mut(4096);
+    let (second, third) = rest.split_at_mut(4096);
+    let mut page_list = PageList::from_segments(
+        &[
+            (first.as_mut_ptr(), first.len()),
+            (second.as_mut_ptr(), second.len()),
+        ],
+        0,
+    );
+    assert_eq!(page_list.verify(), Ok(()));
+    assert_eq!(page_list.page_count(), 2);
+    let first_page = page_list.first_page;
+    // a page that is not in the ring
+    let foreign = PageList::new(third.as_mut_ptr(), third.len());
+    page_list.first_page = foreign.first_page;
+    assert_eq!(page_list.verify(), Err(MaraError::BrokenPageRing));
+    page_list.first_page = first_page;
+    // the ring is cut short
+    let second_page = unsafe { (*first_page).next_page() };
+    unsafe { (*first_page).set_next_page(first_page) };
+    assert_eq!(page_list.verify(), Err(MaraError::BrokenPageRing));
+    assert_eq!(page_list.page_count(), 1);
+    // a cycle that skips the first page and a null link end the walk
+    unsafe { (*first_page).set_next_page(second_page) };
+    unsafe { (*second_page).set_next_page(second_page) };
+    assert_eq!(page_list.verify(), Err(MaraError::BrokenPageRing));
+    assert_eq!(page_list.page_count(), 2);
+    unsafe { (*first_page).set_next_page(core::ptr::null_mut()) };
+    assert_eq!(page_list.verify(), Err(MaraError::BrokenPageRing));
+    assert_eq!(page_list.page_count(), 1);
+    unsafe { (*first_page).set_next_page(second_page) };
+    unsafe { (*second_page).set_next_page(first_page) };
+    assert_eq!(page_list.verify(), Ok(()));
+}
+
+#[test]
+fn test_excluded_ranges_are_no_leaks() {
+    let mut memory = [0u8; 4096];
+    let mut page_list = PageList::new(memory.as_mut_ptr(), memory.len());
+    let start = unsafe { memory.as_mut_ptr().add(2048) };
+    page_list.exclude_range(start, 64);
+    assert_eq!(page_list.leaked_blocks(), 0);
+    let mut alloc_data = AllocationData::new();
+    alloc_data.space.set_size(32);
+    let mut budget = usize::MAX;
+    page_list.dynamic_new(&mut alloc_data, &mut budget);
+    assert_eq!(page_list.leaked_blocks(), 1);
+    page_list.dynamic_delete(alloc_data.space.ptr()).unwrap();
+    assert_eq!(page_list.leaked_blocks(), 0);
 }
@@ -0,0 +1,67 @@
+use crate::block_info::BlockInfo;
+use alloc::vec::Vec;
+
+/// The layout of a heap at one point in time.
+/// Two snapshots can be compared with ``diff``, e.g. to find leaks.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct HeapSnapshot {
+    /// All blocks in the order they are placed in memory
+    pub blocks: Vec<BlockInfo>,
+    /// Sum of the space of all used blocks
+    pub used_bytes: usize,
+    /// Sum of the space of all free blocks
+    pub free_bytes: usize,
+}
+
+/// The used blocks that changed between two snapshots
+#[derive(Debug, Default, Clone, Eq, PartialEq)]
+pub struct HeapDiff {
+    /// used blocks that did not exist before
+    pub allocated: Vec<BlockInfo>,
+    /// used blocks that do not exist anymore
+    pub freed: Vec<BlockInfo>,
+    /// used blocks at the same location with another size (before, after)
+    pub resized: Vec<(BlockInfo, BlockInfo)>,
+}
+
+impl HeapSnapshot {
+    pub fn new(blocks: Vec<BlockInfo>) -> Self {
+        let used_bytes = blocks.iter().filter(|b| !b.free).map(|b| b.size).sum();
+        let free_bytes = blocks.iter().filter(|b| b.free).map(|b| b.size).sum();
+        Self {
+            blocks,
+            used_bytes,
+            free_bytes,
+        }
+    }
+    /// The used block that starts at ``ptr``
+    fn used_block(&self, ptr: *mut u8) -> Option<&BlockInfo> {
+        // blocks are ordered by their address
+        match self
+            .blocks
+            .binary_search_by_key(&(ptr as usize), |b| b.ptr as usize)
+        {
+            Ok(index) if !self.blocks[index].free => Some(&self.blocks[index]),
+            _ => None,
+        }
+    }
+    /// Compares the used blocks of ``self`` (before) with ``later`` (after).
+    /// Free blocks are ignored because splits and merges change them
+    /// without any user action.
+    pub fn diff(&self, later: &HeapSnapshot) -> HeapDiff {
+        let mut diff = HeapDiff::default();
+        for after in later.blocks.iter().filter(|b| !b.free) {
+            match self.used_block(after.ptr) {
+                None => diff.allocated.push(*after),
+                Some(before) if before.size != after.size => diff.resized.push((*before, *after)),
+                Some(_) => {}
+            }
+        }
+        for before in self.blocks.iter().filter(|b| !b.free) {
+            if later.used_block(before.ptr).is_none() {
+                diff.freed.push(*before);
+            }
+        }
+        diff
+    }
+}
@@ -1,6 +1,7 @@
 use crate::code_block;
+use crate::error::MaraError;
 /// Basic Structure:
-/// ```
+/// ```text
 /// Standard Free Space (assuming a next pointer size of 4 byte = 32 bit):
 /// ------------------------------------------------------------------------------------
 /// |.CodeBlock.|.next_pointer.|.........Free Space...........|.next_pointer.|.CodeBlock.|
@@ -59,7 +60,7 @@ impl Space {
     pub fn next(&self) -> Option<Space> {
         match self.next {
             None => panic!("next pointer was not cached earlier"),
-            Some(ptr) if ptr == core::ptr::null_mut() => None,
+            Some(ptr) if ptr.is_null() => None,
             Some(ptr) => Some(Self {
                 ptr: Some(ptr),
                 size: None,
@@ -90,23 +91,29 @@ impl Space {
     /// Writes the pointer stored in ``next`` to the location ``ptr`` points to
     /// The stored pointer will be an offset from start of page.
     /// This is different form the cache method ``set_next``
-    pub fn write_next(&mut self, start_of_page: *const u8) {
-        unsafe {
-            match self.next() {
-                None => *(self.ptr() as *mut NextPointerType) = ERROR_NEXT_POINTER,
-                Some(next) => {
-                    *(self.ptr() as *mut NextPointerType) =
-                        (next.ptr().sub(start_of_page as usize)) as NextPointerType
+    /// Spaces are not aligned so the pointer is written unaligned
+    /// Nothing is written if the offset does not fit in ``NextPointerType``.
+    /// The largest value is reserved for ``ERROR_NEXT_POINTER``.
+    pub fn write_next(&mut self, start_of_page: *const u8) -> Result<(), MaraError> {
+        let next = match self.next() {
+            None => ERROR_NEXT_POINTER,
+            Some(next) => {
+                let offset = next.ptr() as usize - start_of_page as usize;
+                if offset >= ERROR_NEXT_POINTER as usize {
+                    return Err(MaraError::NextPointerOutOfRange);
                 }
+                offset as NextPointerType
             }
-        }
+        };
+        unsafe { (self.ptr() as *mut NextPointerType).write_unaligned(next) };
+        Ok(())
     }
     /// Reads the pointer that is stored at the location of ``ptr``
     /// The stored pointer is an offset from start of page.
     /// This is different from the cache method ``next``
     pub fn read_next(&self, start_of_page: *const u8) -> Option<Space> {
         unsafe {
-            let next = *(self.ptr() as *mut NextPointerType);
+            let next = (self.ptr() as *const NextPointerType).read_unaligned();
             match next {
                 ERROR_NEXT_POINTER => None,
                 ptr => Some(Self {
@@ -117,8 +124,13 @@ impl Space {
             }
         }
     }
+    /// Reads the size from the code block in front of ``ptr``.
+    /// In contrast to ``size`` this ignores the cached value.
+    pub fn size_from_memory(&self) -> usize {
+        unsafe { code_block::read_from_right(self.ptr().sub(1)).0 }
+    }
     pub fn cache_size_from_code_block(&mut self) {
-        unsafe { self.set_size(code_block::read_from_right(self.ptr().sub(1)).0) }
+        self.set_size(self.size_from_memory())
     }
     /// Reads the next pointer at ``ptr`` and stores the encoded address in ``next``
     pub fn cache_next(&mut self, start_of_page: *const u8) {
@@ -129,6 +141,7 @@ impl Space {
     // checks
 
     #[inline]
+    #[cfg_attr(not(feature = "consistency-checks"), allow(unused_variables))]
     pub fn check_size(&self, min: usize, max: usize) {
         #[cfg(feature = "consistency-checks")]
         {
@@ -144,4 +157,56 @@ impl Space {
             }
         }
     }
+    /// Check that the cached size still matches the code block
+    #[inline]
+    pub fn check_cached_size(&self) {
+        #[cfg(feature = "consistency-checks")]
+        {
+            if self.size() != self.size_from_memory() {
+                dbg!(self.size());
+                dbg!(self.size_from_memory());
+                panic!("cached space size differs from code block");
+            }
+        }
+    }
+}
+
+// the free map needs a page
+#[cfg(not(feature = "free_map"))]
+#[test]
+fn test_size_from_memory() {
+    use crate::AllocationData;
+    let mut memory = [0u8; 64];
+    let mut alloc = AllocationData::new();
+    alloc.set_data_start(memory.as_mut_ptr());
+    alloc.set_data_end(unsafe { memory.as_mut_ptr().add(memory.len() - 1) });
+    unsafe { alloc.write_data_size_code_blocks(true) };
+    let mut space = alloc.space;
+    assert_eq!(space.size(), 62);
+    // corrupt the cache
+    space.set_size(3);
+    assert_eq!(space.size_from_memory(), 62);
+}
+
+// a 64 bit offset can not leave the address space
+#[cfg(not(feature = "bit64"))]
+#[test]
+fn test_write_next_out_of_range() {
+    let mut memory = [0u8; 16];
+    let start = memory.as_mut_ptr();
+    let mut space = Space::new();
+    space.set_ptr(unsafe { start.add(1) });
+    let mut next = Space::new();
+    next.set_ptr(unsafe { start.add(8) });
+    space.set_next(Some(next));
+    assert_eq!(space.write_next(start), Ok(()));
+    // the offset collides with ERROR_NEXT_POINTER
+    next.set_ptr(start.wrapping_add(ERROR_NEXT_POINTER as usize));
+    space.set_next(Some(next));
+    assert_eq!(
+        space.write_next(start),
+        Err(MaraError::NextPointerOutOfRange)
+    );
+    // the old pointer is still intact
+    assert_eq!(space.read_next(start).unwrap().ptr(), unsafe { start.add(8) });
 }
@@ -0,0 +1,163 @@
+//! Counters that are collected with the ``statistic`` feature
+
+use core::time::Duration;
+
+/// Usage of the dynamic sector.
+/// Cumulative counters are cleared with ``reset``, peaks with ``reset_peaks``.
+/// The live state is never reset.
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+pub struct Statistics {
+    /// calls of ``dynamic_new`` that returned a block
+    pub allocations: usize,
+    /// calls of ``dynamic_delete``
+    pub frees: usize,
+    /// bytes that were handed out in addition to the requested sizes
+    pub internal_waste: usize,
+    /// the bucket list searches
+    pub search: SearchStats,
+    /// bytes of the space of all used blocks (live)
+    pub allocated_bytes: usize,
+    /// largest value ``allocated_bytes`` had
+    pub peak_allocated_bytes: usize,
+    /// number of used blocks (live)
+    pub live_blocks: usize,
+    /// largest value ``live_blocks`` had
+    pub peak_live_blocks: usize,
+}
+
+impl Statistics {
+    /// Adds an allocation of ``size`` bytes for a request of ``requested`` bytes
+    pub fn record_new(&mut self, requested: usize, size: usize) {
+        self.allocations += 1;
+        self.internal_waste += size - requested;
+        self.allocated_bytes += size;
+        if self.allocated_bytes > self.peak_allocated_bytes {
+            self.peak_allocated_bytes = self.allocated_bytes;
+        }
+        self.live_blocks += 1;
+        if self.live_blocks > self.peak_live_blocks {
+            self.peak_live_blocks = self.live_blocks;
+        }
+    }
+    /// Adds the deletion of a block with ``size`` bytes
+    pub fn record_delete(&mut self, size: usize) {
+        self.frees += 1;
+        self.allocated_bytes -= size;
+        self.live_blocks -= 1;
+    }
+    /// Adds the growth of a block from ``old_size`` to ``new_size`` bytes
+    pub fn record_resize(&mut self, old_size: usize, new_size: usize) {
+        self.allocated_bytes = self.allocated_bytes - old_size + new_size;
+        if self.allocated_bytes > self.peak_allocated_bytes {
+            self.peak_allocated_bytes = self.allocated_bytes;
+        }
+    }
+    /// Clears the cumulative counters
+    pub fn reset(&mut self) {
+        self.allocations = 0;
+        self.frees = 0;
+        self.internal_waste = 0;
+        self.search.reset();
+    }
+    /// Sets the peaks to the current state
+    pub fn reset_peaks(&mut self) {
+        self.peak_allocated_bytes = self.allocated_bytes;
+        self.peak_live_blocks = self.live_blocks;
+        self.search.reset_peak();
+    }
+}
+
+/// How much work the bucket list search did.
+/// Steps are counted like the budget of ``dynamic_new_bounded``:
+/// every skipped empty bucket and every visited free space is one step.
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+pub struct SearchStats {
+    /// number of searches in the bucket list
+    pub searches: usize,
+    /// steps of all searches together
+    pub total_steps: usize,
+    /// steps of the longest search
+    pub max_steps: usize,
+}
+
+impl SearchStats {
+    /// Adds a search that took ``steps`` steps
+    pub fn record(&mut self, steps: usize) {
+        self.searches += 1;
+        self.total_steps += steps;
+        if steps > self.max_steps {
+            self.max_steps = steps;
+        }
+    }
+    /// Adds the searches of ``other``, e.g. of another page
+    pub fn accumulate(&mut self, other: SearchStats) {
+        self.searches += other.searches;
+        self.total_steps += other.total_steps;
+        self.max_steps = self.max_steps.max(other.max_steps);
+    }
+    /// Forgets all searches but keeps the longest one
+    pub fn reset(&mut self) {
+        self.searches = 0;
+        self.total_steps = 0;
+    }
+    /// Forgets the longest search
+    pub fn reset_peak(&mut self) {
+        self.max_steps = 0;
+    }
+    /// Average steps per search, 0 if nothing was searched yet
+    pub fn average(&self) -> f64 {
+        if self.searches == 0 {
+            0.0
+        } else {
+            self.total_steps as f64 / self.searches as f64
+        }
+    }
+}
+
+/// Time spent in the parts of the allocator.
+/// Only measured with ``std``, all durations stay zero with ``no_std``.
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+pub struct TimingBreakdown {
+    /// merging freed blocks with their free neighbors,
+    /// including the bucket updates of the merge
+    pub merge: Duration,
+    /// removing and inserting free spaces in the buckets
+    pub bucket_update: Duration,
+    /// searching the buckets for a fitting free space
+    pub search: Duration,
+}
+
+impl TimingBreakdown {
+    /// Adds the durations of ``other``, e.g. of another page
+    pub fn accumulate(&mut self, other: TimingBreakdown) {
+        self.merge += other.merge;
+        self.bucket_update += other.bucket_update;
+        self.search += other.search;
+    }
+}
+
+/// Measures the duration of a section, does nothing with ``no_std``
+pub struct Stopwatch {
+    #[cfg(not(feature = "no_std"))]
+    start: std::time::Instant,
+}
+
+impl Stopwatch {
+    pub fn start() -> Self {
+        Self {
+            #[cfg(not(feature = "no_std"))]
+            start: std::time::Instant::now(),
+        }
+    }
+    /// Time since ``start``
+    pub fn elapsed(&self) -> Duration {
+        #[cfg(not(feature = "no_std"))]
+        {
+            self.start.elapsed()
+        }
+        #[cfg(feature = "no_std")]
+        {
+            Duration::default()
+        }
+    }
+}
@@ -0,0 +1,25 @@
+use crate::bucket_list::{FitPolicy, InsertionPolicy};
+
+/// The policies that decide where blocks are placed.
+/// They can be swapped at runtime with ``Mara::set_strategy``.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct AllocStrategy {
+    /// How a free block is picked from the free list of its size
+    pub fit: FitPolicy,
+    /// Freed blocks with a smaller space are not merged with their free
+    /// neighbors, 0 merges every block
+    pub coalesce_threshold: usize,
+    /// Where freed blocks are added to the free list of their size
+    pub insertion: InsertionPolicy,
+}
+
+impl Default for AllocStrategy {
+    /// The strategy of a new Mara
+    fn default() -> Self {
+        Self {
+            fit: FitPolicy::FirstFit,
+            coalesce_threshold: 0,
+            insertion: InsertionPolicy::Lifo,
+        }
+    }
+}
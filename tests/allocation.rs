@@ -0,0 +1,1199 @@
+extern crate rusty_mara;
+use rusty_mara::Mara;
+
+#[cfg(not(feature = "bit16"))]
+const MEMORY_SIZE: usize = 0x10_0000;
+// 16 bit next pointers limit a page to 64 KiB
+#[cfg(feature = "bit16")]
+const MEMORY_SIZE: usize = 0xF000;
+
+/// Allocates ``count`` blocks of ``size`` bytes and frees every other one,
+/// so the freed blocks can not be merged.
+/// Returns the blocks that are still in use.
+fn fragment(mara: &Mara, size: usize, count: usize) -> Vec<*mut u8> {
+    let blocks: Vec<*mut u8> = (0..count).map(|_| mara.dynamic_new(size)).collect();
+    let mut used = Vec::new();
+    for (i, ptr) in blocks.into_iter().enumerate() {
+        if i % 2 == 0 {
+            used.push(ptr);
+        } else {
+            mara.dynamic_delete(ptr);
+        }
+    }
+    used
+}
+
+#[test]
+fn test_bounded_allocation_gives_up() {
+    let mut memory: Box<[u8]> = vec![0; MEMORY_SIZE].into_boxed_slice();
+    let mara = Mara::new(memory.as_mut_ptr(), MEMORY_SIZE);
+    // 17 and 20 byte spaces share a bucket, so a 20 byte request has to
+    // walk past all the 17 byte holes
+    let _used = fragment(&mara, 17, 200);
+    assert!(mara.dynamic_new_bounded(20, 10).is_null());
+    // with enough steps the same request succeeds
+    let ptr = mara.dynamic_new_bounded(20, 1000);
+    assert!(!ptr.is_null());
+    mara.dynamic_delete(ptr);
+    // a fitting hole is found right away
+    let ptr = mara.dynamic_new_bounded(17, 10);
+    assert!(!ptr.is_null());
+    mara.dynamic_delete(ptr);
+}
+
+#[test]
+fn test_code_block_overhead_of_tiny_allocations() {
+    const SMALL_MEMORY_SIZE: usize = 0x1000;
+    const COUNT: usize = 100;
+    let mut memory: Box<[u8]> = vec![0; SMALL_MEMORY_SIZE].into_boxed_slice();
+    let mara = Mara::new(memory.as_mut_ptr(), SMALL_MEMORY_SIZE);
+    // the free space of the page needs two byte code blocks
+    assert_eq!(mara.code_block_overhead_bytes(), 2 * 2);
+    for _ in 0..COUNT {
+        mara.dynamic_new(8);
+    }
+    // every tiny block is framed by two single byte code blocks
+    assert_eq!(mara.code_block_overhead_bytes(), 2 * COUNT + 2 * 2);
+}
+
+#[test]
+fn test_no_merge_into_adjacent_page() {
+    const PAGE_SIZE: usize = 0x1000;
+    const MARKER: u8 = 0xEE;
+    let mut memory: Box<[u8]> = vec![0; 2 * PAGE_SIZE].into_boxed_slice();
+    let first = Mara::new(memory.as_mut_ptr(), PAGE_SIZE);
+    let second = Mara::new(unsafe { memory.as_mut_ptr().add(PAGE_SIZE) }, PAGE_SIZE);
+    let neighbor = second.dynamic_new(64);
+    unsafe { core::ptr::write_bytes(neighbor, MARKER, 64) };
+    // freeing merges the blocks up to the end of the first page,
+    // which borders the second page
+    let blocks: Vec<*mut u8> = (0..10).map(|_| first.dynamic_new(64)).collect();
+    let last = first.dynamic_new(PAGE_SIZE / 2);
+    for ptr in blocks {
+        first.dynamic_delete(ptr);
+    }
+    first.dynamic_delete(last);
+    // the whole first page is free again but nothing of the second one
+    let large = first.dynamic_new(PAGE_SIZE / 2);
+    assert!((large as usize) < memory.as_ptr() as usize + PAGE_SIZE);
+    unsafe { core::ptr::write_bytes(large, 0xAB, PAGE_SIZE / 2) };
+    for i in 0..64 {
+        assert_eq!(unsafe { *neighbor.add(i) }, MARKER);
+    }
+    second.dynamic_delete(neighbor);
+    assert_eq!(second.code_block_overhead_bytes(), 2 * 2);
+}
+
+#[cfg(feature = "precommit_next_pointer")]
+#[test]
+fn test_next_pointer_is_precommitted() {
+    const SMALL_MEMORY_SIZE: usize = 0x1000;
+    let mut memory: Box<[u8]> = vec![0; SMALL_MEMORY_SIZE].into_boxed_slice();
+    let mara = Mara::new(memory.as_mut_ptr(), SMALL_MEMORY_SIZE);
+    // without the feature the old next pointer of the free space
+    // (ERROR_NEXT_POINTER, all ones) would still be in the first bytes
+    for size in [4, 16, 100].iter() {
+        let ptr = mara.dynamic_new(*size);
+        for i in 0..4 {
+            assert_eq!(unsafe { *ptr.add(i) }, 0);
+        }
+        mara.dynamic_delete(ptr);
+    }
+}
+
+#[test]
+fn test_tiny_allocations_are_rounded_up() {
+    use rusty_mara::SMALLEST_POSSIBLE_SPACE;
+    const SMALL_MEMORY_SIZE: usize = 0x1000;
+    let mut memory: Box<[u8]> = vec![0; SMALL_MEMORY_SIZE].into_boxed_slice();
+    let mara = Mara::new(memory.as_mut_ptr(), SMALL_MEMORY_SIZE);
+    for size in 1..=SMALLEST_POSSIBLE_SPACE {
+        let ptr = mara.dynamic_new(size);
+        let guard = mara.dynamic_new(16);
+        // the space is large enough for a next pointer
+        let (_, right) = mara.neighbor_blocks(ptr);
+        assert_eq!(
+            right.unwrap().ptr as usize,
+            ptr as usize + SMALLEST_POSSIBLE_SPACE + 2
+        );
+        mara.dynamic_delete(ptr);
+        assert_eq!(mara.verify(), Ok(()));
+        // the freed space can be reused
+        assert_eq!(mara.dynamic_new(size), ptr);
+        mara.dynamic_delete(ptr);
+        mara.dynamic_delete(guard);
+        assert_eq!(mara.verify(), Ok(()));
+    }
+}
+
+#[test]
+fn test_split_keeps_successor_in_bucket() {
+    let mut memory: Box<[u8]> = vec![0; MEMORY_SIZE].into_boxed_slice();
+    let mara = Mara::new(memory.as_mut_ptr(), MEMORY_SIZE);
+    let first = mara.dynamic_new(2000);
+    let _guard = mara.dynamic_new(16);
+    let second = mara.dynamic_new(2000);
+    let _guard = mara.dynamic_new(16);
+    // both are in the largest bucket, first is followed by second
+    mara.dynamic_delete(second);
+    mara.dynamic_delete(first);
+    // split the first one, the block keeps its two byte code blocks
+    let ptr = mara.dynamic_new(200);
+    assert_eq!(ptr, first);
+    let free: Vec<*mut u8> = mara.iter_free_spaces().map(|block| block.ptr).collect();
+    assert_eq!(free.iter().filter(|ptr| **ptr == second).count(), 1);
+    // the remaining part of the first space
+    let (_, remaining) = mara.neighbor_blocks(ptr);
+    assert!(free.contains(&remaining.unwrap().ptr));
+    assert_eq!(mara.verify(), Ok(()));
+}
+
+#[test]
+fn test_allocate_slice_reports_usable_size() {
+    use std::alloc::Layout;
+    const SMALL_MEMORY_SIZE: usize = 0x1000;
+    let mut memory: Box<[u8]> = vec![0; SMALL_MEMORY_SIZE].into_boxed_slice();
+    let mara = Mara::new(memory.as_mut_ptr(), SMALL_MEMORY_SIZE);
+    for size in [1, 4, 17, 100].iter() {
+        let layout = Layout::from_size_align(*size, 1).unwrap();
+        let slice = mara.allocate_slice(layout).unwrap();
+        let len = unsafe { slice.as_ref() }.len();
+        let ptr = slice.as_ptr() as *mut u8;
+        assert!(len >= *size);
+        assert_eq!(len, mara.usable_size(ptr));
+        // the whole slice belongs to the caller
+        unsafe { core::ptr::write_bytes(ptr, 0xAB, len) };
+        assert_eq!(mara.verify(), Ok(()));
+    }
+    // the remaining space is too small for another block, so the
+    // allocation takes all of it
+    let rest = mara.iter_free_spaces().next().unwrap();
+    let layout = Layout::from_size_align(rest.size - 2, 1).unwrap();
+    let slice = mara.allocate_slice(layout).unwrap();
+    assert_eq!(unsafe { slice.as_ref() }.len(), rest.size);
+    // zero sized requests do not touch the heap
+    let empty = mara.allocate_slice(Layout::from_size_align(0, 8).unwrap());
+    assert_eq!(unsafe { empty.unwrap().as_ref() }.len(), 0);
+}
+
+#[test]
+fn test_allocate_slice_is_aligned() {
+    use std::alloc::Layout;
+    let mut memory: Box<[u8]> = vec![0; MEMORY_SIZE].into_boxed_slice();
+    let mara = Mara::new(memory.as_mut_ptr(), MEMORY_SIZE);
+    let mut slices = Vec::new();
+    for &align in [8, 16].iter() {
+        for size in 1..40 {
+            let layout = Layout::from_size_align(size, align).unwrap();
+            let slice = mara.allocate_slice(layout).unwrap();
+            let len = unsafe { slice.as_ref() }.len();
+            let ptr = slice.as_ptr() as *mut u8;
+            assert_eq!(ptr as usize % align, 0);
+            assert!(len >= size);
+            assert_eq!(len, mara.usable_size(ptr));
+            unsafe { core::ptr::write_bytes(ptr, 0xAB, len) };
+            slices.push(ptr);
+        }
+    }
+    assert_eq!(mara.verify(), Ok(()));
+    for ptr in slices {
+        mara.dynamic_delete(ptr);
+    }
+    assert_eq!(mara.iter_blocks().count(), mara.page_count());
+}
+
+#[cfg(feature = "consistency-checks")]
+#[test]
+#[should_panic(expected = "code blocks of the freed block were overwritten")]
+fn test_underrun_is_detected_on_free() {
+    const SMALL_MEMORY_SIZE: usize = 0x1000;
+    let mut memory: Box<[u8]> = vec![0; SMALL_MEMORY_SIZE].into_boxed_slice();
+    let mara = Mara::new(memory.as_mut_ptr(), SMALL_MEMORY_SIZE);
+    let ptr = mara.dynamic_new(16);
+    for _ in 0..10 {
+        mara.dynamic_new(16);
+    }
+    // a used single byte code block of 20 bytes in every configuration
+    unsafe { *ptr.sub(1) = 0b1001_0100 };
+    mara.dynamic_delete(ptr);
+}
+
+#[cfg(feature = "consistency-checks")]
+#[test]
+#[should_panic(expected = "code blocks of the freed block were overwritten")]
+fn test_overrun_is_detected_on_free() {
+    const SMALL_MEMORY_SIZE: usize = 0x1000;
+    let mut memory: Box<[u8]> = vec![0; SMALL_MEMORY_SIZE].into_boxed_slice();
+    let mara = Mara::new(memory.as_mut_ptr(), SMALL_MEMORY_SIZE);
+    let ptr = mara.dynamic_new(16);
+    mara.dynamic_new(16);
+    unsafe { *ptr.add(16) = 0xFF };
+    mara.dynamic_delete(ptr);
+}
+
+#[test]
+fn test_largest_free_block_can_be_allocated() {
+    const SMALL_MEMORY_SIZE: usize = 0x1000;
+    let mut memory: Box<[u8]> = vec![0; SMALL_MEMORY_SIZE].into_boxed_slice();
+    let mara = Mara::new(memory.as_mut_ptr(), SMALL_MEMORY_SIZE);
+    let first = mara.dynamic_new(16);
+    mara.dynamic_new(300);
+    // the free rest of the page needs two byte code blocks
+    let largest = mara.largest_free_block();
+    let rest = mara.iter_free_spaces().next().unwrap();
+    assert_eq!(rest.code_block_size, 2);
+    assert_eq!(largest, rest.size);
+    let ptr = mara.dynamic_new(largest);
+    assert_eq!(ptr, rest.ptr);
+    assert_eq!(mara.largest_free_block(), 0);
+    mara.dynamic_delete(first);
+    assert_eq!(mara.largest_free_block(), 16);
+}
+
+#[test]
+fn test_out_of_memory_reports_largest_free_block() {
+    use rusty_mara::MaraError;
+    const SMALL_MEMORY_SIZE: usize = 0x1000;
+    let mut memory: Box<[u8]> = vec![0; SMALL_MEMORY_SIZE].into_boxed_slice();
+    let mara = Mara::new(memory.as_mut_ptr(), SMALL_MEMORY_SIZE);
+    let small = mara.dynamic_new(20);
+    mara.dynamic_new(16);
+    let large = mara.dynamic_new(100);
+    mara.dynamic_new(16);
+    // use up the rest of the page
+    let rest = mara.iter_free_spaces().next().unwrap();
+    mara.dynamic_new(rest.size);
+    assert_eq!(mara.largest_free_block(), 0);
+
+    mara.dynamic_delete(small);
+    mara.dynamic_delete(large);
+    assert_eq!(mara.largest_free_block(), 100);
+    assert_eq!(
+        mara.try_dynamic_new(101),
+        Err(MaraError::OutOfMemory {
+            largest_available: 100
+        })
+    );
+    assert_eq!(mara.try_dynamic_new(100), Ok(large));
+    assert_eq!(
+        mara.try_dynamic_new(100),
+        Err(MaraError::OutOfMemory {
+            largest_available: 20
+        })
+    );
+}
+
+/// The free block right of ``ptr`` is a valid free space in its bucket
+fn check_free_remainder(mara: &Mara, ptr: *mut u8, size: usize) {
+    use rusty_mara::SMALLEST_POSSIBLE_SPACE;
+    let (_, right) = mara.neighbor_blocks(ptr);
+    let right = right.unwrap();
+    assert!(right.free);
+    assert_eq!(right.size, size);
+    // room for the next pointer
+    assert!(right.size >= SMALLEST_POSSIBLE_SPACE);
+    // findable in its bucket
+    assert!(mara.iter_free_spaces().any(|free| free == right));
+    // both code blocks are valid and equal
+    assert_eq!(mara.verify(), Ok(()));
+}
+
+#[test]
+fn test_split_down_to_smallest_free_space() {
+    use rusty_mara::{SMALLEST_POSSIBLE_FREE_SPACE, SMALLEST_POSSIBLE_SPACE};
+    let mut memory: Box<[u8]> = vec![0; MEMORY_SIZE].into_boxed_slice();
+    let mara = Mara::new(memory.as_mut_ptr(), MEMORY_SIZE);
+    // a 60 byte hole between two used blocks, all sizes have one byte code blocks
+    let left = mara.dynamic_new(16);
+    let hole = mara.dynamic_new(60);
+    mara.dynamic_new(16);
+    mara.dynamic_delete(hole);
+
+    // every split cuts the requested space plus two code blocks from the hole
+    let a = mara.dynamic_new(20);
+    assert_eq!(a, hole);
+    check_free_remainder(&mara, a, 38);
+    let b = mara.dynamic_new(10);
+    check_free_remainder(&mara, b, 26);
+    // leaves exactly the smallest free space behind
+    let c = mara.dynamic_new(26 - SMALLEST_POSSIBLE_FREE_SPACE);
+    check_free_remainder(&mara, c, SMALLEST_POSSIBLE_FREE_SPACE - 2);
+    assert_eq!(SMALLEST_POSSIBLE_FREE_SPACE - 2, SMALLEST_POSSIBLE_SPACE);
+
+    // and back up by merging
+    mara.dynamic_delete(c);
+    check_free_remainder(&mara, b, 26);
+    mara.dynamic_delete(b);
+    check_free_remainder(&mara, a, 38);
+    mara.dynamic_delete(a);
+    check_free_remainder(&mara, left, 60);
+}
+
+#[test]
+fn test_alloc_aligned_zeroed() {
+    const SMALL_MEMORY_SIZE: usize = 0x4000;
+    let mut memory: Box<[u8]> = vec![0; SMALL_MEMORY_SIZE].into_boxed_slice();
+    let mara = Mara::new(memory.as_mut_ptr(), SMALL_MEMORY_SIZE);
+    // from memory that was never handed out
+    let fresh = mara.alloc_aligned_zeroed(100, 64);
+    assert_eq!(fresh as usize % 64, 0);
+    assert!(unsafe { core::slice::from_raw_parts(fresh, 100) }
+        .iter()
+        .all(|&byte| byte == 0));
+    // from memory that was used before
+    let dirty = mara.dynamic_new(1000);
+    unsafe { core::ptr::write_bytes(dirty, 0xFF, 1000) };
+    mara.dynamic_delete(dirty);
+    for &align in &[1, 2, 8, 16, 256, 1024] {
+        let ptr = mara.alloc_aligned_zeroed(200, align);
+        assert!(!ptr.is_null());
+        assert_eq!(ptr as usize % align, 0);
+        assert!(unsafe { core::slice::from_raw_parts(ptr, 200) }
+            .iter()
+            .all(|&byte| byte == 0));
+        assert!(mara.usable_size(ptr) >= 200);
+        assert_eq!(mara.verify(), Ok(()));
+        mara.dynamic_delete(ptr);
+    }
+    mara.dynamic_delete(fresh);
+    assert_eq!(mara.verify(), Ok(()));
+    // everything merged again
+    assert_eq!(mara.iter_blocks().count(), 1);
+    // no free space can hold the block at an aligned position
+    let ptr = mara.alloc_aligned_zeroed(SMALL_MEMORY_SIZE / 2, SMALL_MEMORY_SIZE);
+    assert!(ptr.is_null());
+}
+
+#[test]
+fn test_overflow_bucket_prefers_the_smallest_fitting_space() {
+    let mut memory: Box<[u8]> = vec![0; MEMORY_SIZE].into_boxed_slice();
+    let mara = Mara::new(memory.as_mut_ptr(), MEMORY_SIZE);
+    // the hole is added behind the rest of the page
+    mara.set_insertion_policy(rusty_mara::InsertionPolicy::Fifo);
+    mara.dynamic_new(16);
+    let hole = mara.dynamic_new(2000);
+    mara.dynamic_new(16);
+    mara.dynamic_delete(hole);
+    let largest = mara.largest_free_block();
+    // both fit, but only the hole is not split
+    assert_eq!(mara.dynamic_new(1500), hole);
+    assert_eq!(mara.largest_free_block(), largest);
+}
+
+#[test]
+fn test_relocate_into_lower_free_space() {
+    let mut memory: Box<[u8]> = vec![0; MEMORY_SIZE].into_boxed_slice();
+    let mara = Mara::new(memory.as_mut_ptr(), MEMORY_SIZE);
+    mara.dynamic_new(16);
+    // small enough for one byte code blocks like the moved block
+    let hole = mara.dynamic_new(60);
+    mara.dynamic_new(16);
+    let block = mara.dynamic_new(40);
+    let last = mara.dynamic_new(16);
+    unsafe { core::ptr::write_bytes(block, 0x5A, 40) };
+    // nothing lower is free
+    let mut new_ptr = core::ptr::null_mut();
+    assert!(!mara.relocate(block, &mut new_ptr));
+    assert!(new_ptr.is_null());
+
+    mara.dynamic_delete(hole);
+    assert!(mara.relocate(block, &mut new_ptr));
+    assert_eq!(new_ptr, hole);
+    assert!(unsafe { core::slice::from_raw_parts(new_ptr, 40) }
+        .iter()
+        .all(|&byte| byte == 0x5A));
+    // the old block is free again
+    let (old, _) = mara.neighbor_blocks(last);
+    let old = old.unwrap();
+    assert!(old.free);
+    assert_eq!(old.ptr, block);
+    assert_eq!(mara.verify(), Ok(()));
+    // the rest of the hole is behind the moved block
+    assert!(!mara.relocate(new_ptr, &mut new_ptr));
+}
+
+#[test]
+#[cfg(feature = "consistency-checks")]
+#[should_panic(expected = "Mara used before initialization")]
+fn test_zeroed_mara_is_detected() {
+    let mara: Mara = unsafe { core::mem::zeroed() };
+    mara.dynamic_new(16);
+}
+
+#[test]
+fn test_alloc_from_bucket() {
+    let mut memory: Box<[u8]> = vec![0; MEMORY_SIZE].into_boxed_slice();
+    let mara = Mara::new(memory.as_mut_ptr(), MEMORY_SIZE);
+    let bucket = Mara::bucket_index(24);
+    assert_eq!(mara.alloc_from_bucket(bucket), None);
+    // warm the bucket with ten freed blocks
+    let _used = fragment(&mara, 24, 21);
+    let block_count = mara.iter_blocks().count();
+    let mut taken = Vec::new();
+    while let Some(ptr) = mara.alloc_from_bucket(bucket) {
+        assert_eq!(mara.usable_size(ptr), 24);
+        taken.push(ptr);
+    }
+    assert_eq!(taken.len(), 10);
+    // no block was split
+    assert_eq!(mara.iter_blocks().count(), block_count);
+    assert_eq!(mara.verify(), Ok(()));
+}
+
+#[test]
+fn test_compact() {
+    const SMALL_MEMORY_SIZE: usize = 0x4000;
+    let mut memory: Box<[u8]> = vec![0; SMALL_MEMORY_SIZE].into_boxed_slice();
+    let mara = Mara::new(memory.as_mut_ptr(), SMALL_MEMORY_SIZE);
+    let blocks: Vec<(*mut u8, usize)> = (0..40)
+        .map(|i| {
+            // small and large blocks, so some are moved into smaller gaps
+            let size = if i % 3 == 0 { 200 } else { 10 + i };
+            let ptr = mara.dynamic_new(size);
+            unsafe { core::ptr::write_bytes(ptr, i as u8, size) };
+            (ptr, size)
+        })
+        .collect();
+    // free every other block
+    for &(ptr, _) in blocks.iter().step_by(2) {
+        mara.dynamic_delete(ptr);
+    }
+    let mut live: Vec<(*mut u8, usize, u8)> = blocks
+        .into_iter()
+        .enumerate()
+        .filter(|(i, _)| i % 2 == 1)
+        .map(|(i, (ptr, size))| (ptr, size, i as u8))
+        .collect();
+    let mut moved = 0;
+    mara.compact(|old, new| {
+        let block = live.iter_mut().find(|block| block.0 == old).unwrap();
+        block.0 = new;
+        moved += 1;
+    });
+    assert_eq!(moved, live.len());
+    assert_eq!(mara.verify(), Ok(()));
+    // one free block at the end of the page
+    let free: Vec<_> = mara.iter_blocks().filter(|block| block.free).collect();
+    assert_eq!(free.len(), 1);
+    assert_eq!(mara.iter_blocks().last(), Some(free[0]));
+    for &(ptr, size, content) in &live {
+        assert!(mara.usable_size(ptr) >= size);
+        assert!(unsafe { core::slice::from_raw_parts(ptr, size) }
+            .iter()
+            .all(|&byte| byte == content));
+        mara.dynamic_delete(ptr);
+    }
+    assert_eq!(mara.iter_blocks().count(), 1);
+}
+
+#[test]
+fn test_user_data() {
+    const SMALL_MEMORY_SIZE: usize = 0x1000;
+    let mut memory: Box<[u8]> = vec![0; SMALL_MEMORY_SIZE].into_boxed_slice();
+    let mut mara = Mara::with_user_data(memory.as_mut_ptr(), SMALL_MEMORY_SIZE, 16);
+    let user_data = mara.user_data(0);
+    assert_eq!(user_data.len(), 16);
+    user_data.copy_from_slice(&[0xA5; 16]);
+    let user_data_end = user_data.as_ptr() as usize + 16;
+    // the first block starts behind the user data
+    let first = mara.iter_blocks().next().unwrap();
+    assert_eq!(first.ptr as usize - first.code_block_size, user_data_end);
+    // fill the page
+    while let Ok(ptr) = mara.try_dynamic_new(32) {
+        assert!(ptr as usize > user_data_end);
+        unsafe { core::ptr::write_bytes(ptr, 0xFF, 32) };
+    }
+    let rest = mara.largest_free_block();
+    if rest > 0 {
+        let ptr = mara.dynamic_new(rest);
+        unsafe { core::ptr::write_bytes(ptr, 0xFF, rest) };
+    }
+    assert_eq!(mara.user_data(0), &[0xA5; 16]);
+    assert_eq!(mara.verify(), Ok(()));
+    // nothing is reserved by default
+    let mut mara = Mara::new(memory.as_mut_ptr(), SMALL_MEMORY_SIZE);
+    assert!(mara.user_data(0).is_empty());
+}
+
+#[test]
+fn test_request_exceeding_the_page() {
+    use rusty_mara::MaraError;
+    const SMALL_MEMORY_SIZE: usize = 0x1000;
+    let mut memory: Box<[u8]> = vec![0; SMALL_MEMORY_SIZE].into_boxed_slice();
+    let mara = Mara::new(memory.as_mut_ptr(), SMALL_MEMORY_SIZE);
+    let max_allocation = mara.max_allocation_size();
+    // the page struct and the code blocks of the first block are not usable
+    assert_eq!(mara.largest_free_block(), max_allocation);
+    assert!(max_allocation < SMALL_MEMORY_SIZE);
+    for size in max_allocation + 1..SMALL_MEMORY_SIZE {
+        assert_eq!(
+            mara.try_dynamic_new(size),
+            Err(MaraError::ExceedsMaxAllocation { max_allocation })
+        );
+    }
+    let block = mara.try_dynamic_new(max_allocation).unwrap();
+    // a full heap is reported differently
+    assert_eq!(
+        mara.try_dynamic_new(max_allocation),
+        Err(MaraError::OutOfMemory {
+            largest_available: 0
+        })
+    );
+    mara.dynamic_delete(block);
+    assert!(mara.try_dynamic_new(max_allocation).is_ok());
+}
+
+#[test]
+fn test_dynamic_new_zeroed() {
+    const SMALL_MEMORY_SIZE: usize = 0x1000;
+    let mut memory: Box<[u8]> = vec![0; SMALL_MEMORY_SIZE].into_boxed_slice();
+    let mara = Mara::new(memory.as_mut_ptr(), SMALL_MEMORY_SIZE);
+    let hole = mara.dynamic_new(40);
+    let neighbor = mara.dynamic_new(16);
+    unsafe {
+        core::ptr::write_bytes(hole, 0xAB, 40);
+        core::ptr::write_bytes(neighbor, 0xCD, 16);
+    }
+    mara.dynamic_delete(hole);
+    // the remainder is too small to be split off, the whole hole is handed out
+    let ptr = mara.dynamic_new_zeroed(38);
+    assert_eq!(ptr, hole);
+    let size = mara.usable_size(ptr);
+    assert_eq!(size, 40);
+    let space = unsafe { core::slice::from_raw_parts(ptr, size) };
+    assert!(space.iter().all(|&byte| byte == 0));
+    // the code blocks behind the space are intact
+    assert_eq!(mara.neighbor_blocks(ptr).1.unwrap().ptr, neighbor);
+    let neighbor = unsafe { core::slice::from_raw_parts(neighbor, 16) };
+    assert!(neighbor.iter().all(|&byte| byte == 0xCD));
+    assert_eq!(mara.verify(), Ok(()));
+}
+
+#[test]
+fn test_dynamic_resize() {
+    const SMALL_MEMORY_SIZE: usize = 0x1000;
+    let mut memory: Box<[u8]> = vec![0; SMALL_MEMORY_SIZE].into_boxed_slice();
+    let mara = Mara::new(memory.as_mut_ptr(), SMALL_MEMORY_SIZE);
+    let fill = |ptr: *mut u8, size: usize| unsafe { core::ptr::write_bytes(ptr, 0xAB, size) };
+    let filled = |ptr: *mut u8, size: usize| unsafe {
+        core::slice::from_raw_parts(ptr, size)
+            .iter()
+            .all(|&byte| byte == 0xAB)
+    };
+    // grow into a free neighbor, the rest is split off
+    let block = mara.dynamic_new(32);
+    let neighbor = mara.dynamic_new(64);
+    let guard = mara.dynamic_new(16);
+    fill(block, 32);
+    mara.dynamic_delete(neighbor);
+    assert_eq!(mara.dynamic_resize(block, 60), block);
+    assert_eq!(mara.usable_size(block), 60);
+    assert!(filled(block, 32));
+    let rest = mara.neighbor_blocks(block).1.unwrap();
+    assert!(rest.free);
+    assert_eq!(mara.neighbor_blocks(rest.ptr).1.unwrap().ptr, guard);
+    assert_eq!(mara.verify(), Ok(()));
+    mara.dynamic_delete(block);
+    mara.dynamic_delete(guard);
+
+    // the free neighbor is too small, the block is moved
+    let block = mara.dynamic_new(32);
+    let neighbor = mara.dynamic_new(8);
+    let guard = mara.dynamic_new(16);
+    fill(block, 32);
+    mara.dynamic_delete(neighbor);
+    let moved = mara.dynamic_resize(block, 100);
+    assert_ne!(moved, block);
+    assert!(mara.usable_size(moved) >= 100);
+    assert!(filled(moved, 32));
+    // the old block was freed and merged with its neighbor
+    assert!(mara.neighbor_blocks(guard).0.unwrap().free);
+    assert_eq!(mara.verify(), Ok(()));
+    mara.dynamic_delete(moved);
+    mara.dynamic_delete(guard);
+
+    // the last block of the page has no right neighbor
+    let hole = mara.dynamic_new(200);
+    mara.dynamic_new(mara.largest_free_block() - 100);
+    let last = mara.dynamic_new(mara.largest_free_block());
+    assert_eq!(mara.neighbor_blocks(last).1, None);
+    let size = mara.usable_size(last);
+    fill(last, size);
+    assert!(mara.dynamic_resize(last, size + 50).is_null());
+    assert!(filled(last, size));
+    mara.dynamic_delete(hole);
+    let moved = mara.dynamic_resize(last, size + 50);
+    assert_eq!(moved, hole);
+    assert!(filled(moved, size));
+    assert_eq!(mara.verify(), Ok(()));
+}
+
+#[test]
+fn test_allocation_data() {
+    const SMALL_MEMORY_SIZE: usize = 0x1000;
+    let mut memory: Box<[u8]> = vec![0; SMALL_MEMORY_SIZE].into_boxed_slice();
+    let mara = Mara::new(memory.as_mut_ptr(), SMALL_MEMORY_SIZE);
+    let small = mara.dynamic_new(20);
+    let large = mara.dynamic_new(300);
+    let free = mara.dynamic_new(20);
+    mara.dynamic_new(20);
+    mara.dynamic_delete(free);
+
+    let data = unsafe { mara.allocation_data(small) }.unwrap();
+    assert_eq!(data.space.ptr(), small);
+    assert_eq!(data.space.size(), 20);
+    assert_eq!(data.code_block_size(), 1);
+    assert_eq!(data.data_start(), unsafe { small.sub(1) });
+    assert_eq!(data.data_end(), unsafe { small.add(20) });
+
+    let data = unsafe { mara.allocation_data(large) }.unwrap();
+    assert_eq!(data.space.size(), 300);
+    assert_eq!(data.code_block_size(), 2);
+    assert_eq!(data.data_start(), unsafe { large.sub(2) });
+    assert_eq!(data.data_end(), unsafe { large.add(301) });
+
+    // only the start of a used space is accepted
+    assert_eq!(unsafe { mara.allocation_data(large.add(1)) }, None);
+    assert_eq!(unsafe { mara.allocation_data(free) }, None);
+    assert_eq!(unsafe { mara.allocation_data(core::ptr::null_mut()) }, None);
+}
+
+#[test]
+fn test_dynamic_resize_shrinks_in_place() {
+    use rusty_mara::SMALLEST_POSSIBLE_FREE_SPACE;
+    const SMALL_MEMORY_SIZE: usize = 0x1000;
+    let mut memory: Box<[u8]> = vec![0; SMALL_MEMORY_SIZE].into_boxed_slice();
+    let mara = Mara::new(memory.as_mut_ptr(), SMALL_MEMORY_SIZE);
+    let block = mara.dynamic_new(40);
+    let guard = mara.dynamic_new(16);
+    unsafe { core::ptr::write_bytes(block, 0xAB, 40) };
+    // the tail is too small to be freed
+    assert_eq!(
+        mara.dynamic_resize(block, 40 - SMALLEST_POSSIBLE_FREE_SPACE + 1),
+        block
+    );
+    assert_eq!(mara.usable_size(block), 40);
+    // the tail is freed
+    assert_eq!(mara.dynamic_resize(block, 20), block);
+    assert_eq!(mara.usable_size(block), 20);
+    let tail = mara.neighbor_blocks(block).1.unwrap();
+    assert!(tail.free);
+    assert_eq!(mara.neighbor_blocks(tail.ptr).1.unwrap().ptr, guard);
+    let content = unsafe { core::slice::from_raw_parts(block, 20) };
+    assert!(content.iter().all(|&byte| byte == 0xAB));
+    // a tail in front of a free neighbor is merged with it
+    let large = mara.dynamic_new(40);
+    assert_eq!(mara.dynamic_resize(large, 10), large);
+    let rest = mara.neighbor_blocks(large).1.unwrap();
+    assert!(rest.free);
+    assert_eq!(mara.neighbor_blocks(rest.ptr).1, None);
+    assert_eq!(mara.iter_free_spaces().count(), 2);
+    // the code blocks of a large block can not shrink in place
+    mara.dynamic_delete(large);
+    let large = mara.dynamic_new(300);
+    assert_eq!(mara.dynamic_resize(large, 10), large);
+    let two_byte_code_blocks = (rusty_mara::SMALLEST_POSSIBLE_SPACE..)
+        .find(|&size| Mara::overhead_for(size) == 4)
+        .unwrap();
+    assert_eq!(mara.usable_size(large), two_byte_code_blocks);
+    assert_eq!(mara.verify(), Ok(()));
+}
+
+#[test]
+fn test_static_new() {
+    const SMALL_MEMORY_SIZE: usize = 0x1000;
+    let mut memory: Box<[u8]> = vec![0; SMALL_MEMORY_SIZE].into_boxed_slice();
+    let end_of_memory = unsafe { memory.as_mut_ptr().add(SMALL_MEMORY_SIZE) };
+    let mara = Mara::new(memory.as_mut_ptr(), SMALL_MEMORY_SIZE);
+    let max_allocation = mara.max_allocation_size();
+    // the static sector grows down from the end of the page
+    let first = mara.static_new(100);
+    assert_eq!(first, unsafe { end_of_memory.sub(100) });
+    let second = mara.static_new(50);
+    assert_eq!(second, unsafe { first.sub(50) });
+    unsafe {
+        core::ptr::write_bytes(first, 0xA5, 100);
+        core::ptr::write_bytes(second, 0x5A, 50);
+    }
+    assert!(mara.max_allocation_size() <= max_allocation - 150);
+    assert_eq!(mara.largest_free_block(), mara.max_allocation_size());
+    // dynamic blocks stay below the static sector
+    while let Ok(ptr) = mara.try_dynamic_new(32) {
+        assert!(ptr as usize + mara.usable_size(ptr) < second as usize);
+        unsafe { core::ptr::write_bytes(ptr, 0xFF, 32) };
+    }
+    let rest = mara.largest_free_block();
+    if rest > 0 {
+        let ptr = mara.dynamic_new(rest);
+        unsafe { core::ptr::write_bytes(ptr, 0xFF, rest) };
+    }
+    let first = unsafe { core::slice::from_raw_parts(first, 100) };
+    let second = unsafe { core::slice::from_raw_parts(second, 50) };
+    assert!(first.iter().all(|&byte| byte == 0xA5));
+    assert!(second.iter().all(|&byte| byte == 0x5A));
+    assert_eq!(mara.verify(), Ok(()));
+    // the last block of the page is used now
+    assert!(mara.static_new(1).is_null());
+}
+
+#[test]
+fn test_dynamic_new_batch() {
+    let mut memory: Box<[u8]> = vec![0; MEMORY_SIZE].into_boxed_slice();
+    let mara = Mara::new(memory.as_mut_ptr(), MEMORY_SIZE);
+    let mut blocks = [core::ptr::null_mut(); 100];
+    let mut reported = Vec::new();
+    let count = mara.dynamic_new_batch_with_progress(16, &mut blocks, 10, |count| {
+        reported.push(count);
+        count < 50
+    });
+    assert_eq!(count, 50);
+    assert_eq!(reported, vec![10, 20, 30, 40, 50]);
+    assert!(blocks[..50].iter().all(|ptr| !ptr.is_null()));
+    assert!(blocks[50..].iter().all(|ptr| ptr.is_null()));
+    assert_eq!(mara.iter_blocks().filter(|block| !block.free).count(), 50);
+
+    // a full heap ends the batch
+    const SMALL_MEMORY_SIZE: usize = 0x1000;
+    let mut memory: Box<[u8]> = vec![0; SMALL_MEMORY_SIZE].into_boxed_slice();
+    let mara = Mara::new(memory.as_mut_ptr(), SMALL_MEMORY_SIZE);
+    let mut blocks = [core::ptr::null_mut(); 1000];
+    let count = mara.dynamic_new_batch(64, &mut blocks);
+    assert!(count > 0 && count < 1000);
+    assert_eq!(mara.iter_blocks().filter(|block| !block.free).count(), count);
+    assert!(mara.largest_free_block() < 64);
+}
+
+#[test]
+fn test_out_of_memory_returns_null() {
+    use std::alloc::{GlobalAlloc, Layout};
+    const SMALL_MEMORY_SIZE: usize = 0x1000;
+    let mut memory: Box<[u8]> = vec![0; SMALL_MEMORY_SIZE].into_boxed_slice();
+    let mara = Mara::new(memory.as_mut_ptr(), SMALL_MEMORY_SIZE);
+    let layout = Layout::from_size_align(32, 1).unwrap();
+    let mut count = 0;
+    loop {
+        let ptr = unsafe { mara.alloc(layout) };
+        if ptr.is_null() {
+            break;
+        }
+        count += 1;
+    }
+    assert!(count > 0);
+    // every further request fails the same way
+    assert!(unsafe { mara.alloc(layout) }.is_null());
+    assert!(unsafe { mara.alloc_zeroed(layout) }.is_null());
+    assert!(mara.dynamic_new(32).is_null());
+    assert_eq!(mara.allocate_slice(layout), None);
+    assert_eq!(mara.verify(), Ok(()));
+}
+
+#[test]
+fn test_try_dynamic_delete() {
+    use rusty_mara::MaraError;
+    const SMALL_MEMORY_SIZE: usize = 0x1000;
+    let mut memory: Box<[u8]> = vec![0; SMALL_MEMORY_SIZE].into_boxed_slice();
+    let mara = Mara::new(memory.as_mut_ptr(), SMALL_MEMORY_SIZE);
+    let ptr = mara.dynamic_new(32);
+    let mut outside = [0u8; 64];
+    assert_eq!(
+        mara.try_dynamic_delete(outside.as_mut_ptr()),
+        Err(MaraError::InvalidFree)
+    );
+    assert_eq!(
+        mara.try_dynamic_delete(core::ptr::null_mut()),
+        Err(MaraError::InvalidFree)
+    );
+    // the heap is untouched
+    assert_eq!(mara.usable_size(ptr), 32);
+    assert_eq!(mara.verify(), Ok(()));
+    assert_eq!(mara.try_dynamic_delete(ptr), Ok(()));
+    assert_eq!(mara.iter_blocks().count(), 1);
+}
+
+#[test]
+fn test_double_free_is_refused() {
+    use rusty_mara::MaraError;
+    const SMALL_MEMORY_SIZE: usize = 0x1000;
+    let mut memory: Box<[u8]> = vec![0; SMALL_MEMORY_SIZE].into_boxed_slice();
+    let mara = Mara::new(memory.as_mut_ptr(), SMALL_MEMORY_SIZE);
+    let _left = mara.dynamic_new(16);
+    let ptr = mara.dynamic_new(32);
+    let _right = mara.dynamic_new(16);
+    assert_eq!(mara.try_dynamic_delete(ptr), Ok(()));
+    let blocks: Vec<_> = mara.iter_blocks().collect();
+    assert_eq!(mara.try_dynamic_delete(ptr), Err(MaraError::DoubleFree));
+    // the free block is neither merged nor inserted a second time
+    assert_eq!(mara.iter_blocks().collect::<Vec<_>>(), blocks);
+    assert_eq!(mara.iter_free_spaces().count(), 2);
+    assert_eq!(mara.verify(), Ok(()));
+    // and can still be reused
+    assert_eq!(mara.dynamic_new(32), ptr);
+}
+
+#[test]
+fn test_interior_pointer_is_refused() {
+    use rusty_mara::MaraError;
+    const SMALL_MEMORY_SIZE: usize = 0x1000;
+    let mut memory: Box<[u8]> = vec![0; SMALL_MEMORY_SIZE].into_boxed_slice();
+    let mara = Mara::new(memory.as_mut_ptr(), SMALL_MEMORY_SIZE);
+    let ptr = mara.dynamic_new(64);
+    let _right = mara.dynamic_new(16);
+    let blocks: Vec<_> = mara.iter_blocks().collect();
+    // zeroed user data decodes to a block that is too small
+    unsafe { core::ptr::write_bytes(ptr, 0, 64) };
+    let interior = unsafe { ptr.add(8) };
+    assert_eq!(mara.try_dynamic_delete(interior), Err(MaraError::InvalidFree));
+    // this user data decodes to a block that is larger than the page
+    unsafe { core::ptr::write_bytes(ptr, 0x7F, 64) };
+    assert_eq!(mara.try_dynamic_delete(interior), Err(MaraError::InvalidFree));
+    // the heap is untouched
+    assert_eq!(mara.iter_blocks().collect::<Vec<_>>(), blocks);
+    assert_eq!(mara.verify(), Ok(()));
+    assert_eq!(mara.try_dynamic_delete(ptr), Ok(()));
+}
+
+#[test]
+fn test_interior_pointer_between_forged_code_blocks_is_refused() {
+    use rusty_mara::MaraError;
+    const SMALL_MEMORY_SIZE: usize = 0x1000;
+    let mut memory: Box<[u8]> = vec![0; SMALL_MEMORY_SIZE].into_boxed_slice();
+    let mara = Mara::new(memory.as_mut_ptr(), SMALL_MEMORY_SIZE);
+    let ptr = mara.dynamic_new(64);
+    let small = mara.dynamic_new(16);
+    let blocks: Vec<_> = mara.iter_blocks().collect();
+    // the user data holds the code blocks of a used block of 16 bytes
+    let interior = unsafe { ptr.add(8) };
+    unsafe {
+        let code_block = *small.sub(1);
+        *interior.sub(1) = code_block;
+        *interior.add(16) = code_block;
+    }
+    assert_eq!(mara.try_dynamic_delete(interior), Err(MaraError::InvalidFree));
+    assert_eq!(mara.iter_blocks().collect::<Vec<_>>(), blocks);
+    assert_eq!(mara.verify(), Ok(()));
+    assert_eq!(mara.try_dynamic_delete(ptr), Ok(()));
+    assert_eq!(mara.try_dynamic_delete(small), Ok(()));
+}
+
+#[test]
+fn test_static_block_is_refused() {
+    use rusty_mara::MaraError;
+    const SMALL_MEMORY_SIZE: usize = 0x1000;
+    let mut memory: Box<[u8]> = vec![0; SMALL_MEMORY_SIZE].into_boxed_slice();
+    let mara = Mara::new(memory.as_mut_ptr(), SMALL_MEMORY_SIZE);
+    let ptr = mara.dynamic_new(32);
+    let static_block = mara.static_new(32);
+    let blocks: Vec<_> = mara.iter_blocks().collect();
+    assert_eq!(
+        mara.try_dynamic_delete(static_block),
+        Err(MaraError::InvalidFree)
+    );
+    // the heap is untouched
+    assert_eq!(mara.iter_blocks().collect::<Vec<_>>(), blocks);
+    assert_eq!(mara.verify(), Ok(()));
+    assert_eq!(mara.try_dynamic_delete(ptr), Ok(()));
+}
+
+#[test]
+#[should_panic(expected = "the freed pointer is not in any page")]
+fn test_dynamic_delete_of_a_foreign_pointer() {
+    const SMALL_MEMORY_SIZE: usize = 0x1000;
+    let mut memory: Box<[u8]> = vec![0; SMALL_MEMORY_SIZE].into_boxed_slice();
+    let mara = Mara::new(memory.as_mut_ptr(), SMALL_MEMORY_SIZE);
+    let mut outside = [0u8; 64];
+    mara.dynamic_delete(outside.as_mut_ptr());
+}
+
+#[test]
+fn test_usable_size_of_an_enlarged_block() {
+    use rusty_mara::SMALLEST_POSSIBLE_FREE_SPACE;
+    let mut memory: Box<[u8]> = vec![0; MEMORY_SIZE].into_boxed_slice();
+    let mara = Mara::new(memory.as_mut_ptr(), MEMORY_SIZE);
+    let first = mara.dynamic_new(40);
+    let _second = mara.dynamic_new(40);
+    assert_eq!(mara.usable_size(first), 40);
+    mara.dynamic_delete(first);
+    // the rest of a split would be too small to be managed as free space,
+    // so the whole block is handed out
+    let enlarged = mara.dynamic_new(40 - SMALLEST_POSSIBLE_FREE_SPACE + 1);
+    assert_eq!(enlarged, first);
+    assert_eq!(mara.usable_size(enlarged), 40);
+}
+
+#[test]
+fn test_owns() {
+    let mut memory: Box<[u8]> = vec![0; MEMORY_SIZE].into_boxed_slice();
+    let mara = Mara::new(memory.as_mut_ptr(), MEMORY_SIZE);
+    let block = mara.dynamic_new(40);
+    let static_block = mara.static_new(40);
+    assert!(mara.owns(block));
+    assert!(!mara.owns(unsafe { block.add(1) }));
+    assert!(!mara.owns(static_block));
+    let mut outside = [0u8; 64];
+    assert!(!mara.owns(outside.as_mut_ptr()));
+    mara.dynamic_delete(block);
+    assert!(!mara.owns(block));
+}
+
+#[test]
+fn test_reset() {
+    let mut memory: Box<[u8]> = vec![0; MEMORY_SIZE].into_boxed_slice();
+    // a single page, so the fragmented page holds the largest free block
+    let mara = Mara::with_page_size(memory.as_mut_ptr(), MEMORY_SIZE, MEMORY_SIZE);
+    let static_block = mara.static_new(16);
+    unsafe { core::ptr::write_bytes(static_block, 0xAB, 16) };
+    let initial_free = mara.largest_free_block();
+    let _used = fragment(&mara, 24, 100);
+    assert!(mara.largest_free_block() < initial_free);
+    mara.reset();
+    assert_eq!(mara.iter_blocks().count(), 1);
+    assert_eq!(mara.largest_free_block(), initial_free);
+    assert_eq!(mara.verify(), Ok(()));
+    // the static sector survives
+    assert!(unsafe { core::slice::from_raw_parts(static_block, 16) }
+        .iter()
+        .all(|&byte| byte == 0xAB));
+    // the heap is usable as before
+    let block = mara.dynamic_new(initial_free);
+    assert!(!block.is_null());
+    mara.dynamic_delete(block);
+}
+
+#[test]
+fn test_can_fit() {
+    const SIZE: usize = 0x1000;
+    let mut memory: Box<[u8]> = vec![0; SIZE].into_boxed_slice();
+    let mara = Mara::new(memory.as_mut_ptr(), SIZE);
+    assert_eq!(Mara::overhead_for(24), 2);
+    assert_eq!(
+        Mara::overhead_for(1),
+        rusty_mara::SMALLEST_POSSIBLE_SPACE - 1 + 2
+    );
+    // fill the heap to learn how many blocks really fit
+    let mut count = 0;
+    while !mara.dynamic_new(24).is_null() {
+        count += 1;
+    }
+    assert!(mara.can_fit(&[(24, count)]));
+    assert!(!mara.can_fit(&[(24, count + 1)]));
+    assert!(mara.can_fit(&[(24, count - 2), (48, 1)]));
+    assert!(!mara.can_fit(&[(mara.max_allocation_size() + 1, 1)]));
+    assert!(mara.can_fit(&[]));
+}
+
+#[test]
+fn test_drop_with_live_blocks() {
+    let mut memory: Box<[u8]> = vec![0; MEMORY_SIZE].into_boxed_slice();
+    let mara = Mara::new(memory.as_mut_ptr(), MEMORY_SIZE);
+    mara.dynamic_new(16);
+    mara.dynamic_new(32);
+    // leaks are only reported, the memory stays untouched
+    drop(mara);
+    let zeroed: Mara = unsafe { core::mem::zeroed() };
+    drop(zeroed);
+}
+
+#[test]
+fn test_zero_size_requests_do_not_touch_the_heap() {
+    use std::alloc::{GlobalAlloc, Layout};
+    let mut memory: Box<[u8]> = vec![0; MEMORY_SIZE].into_boxed_slice();
+    let mara = Mara::new(memory.as_mut_ptr(), MEMORY_SIZE);
+    let free = mara.total_free_bytes();
+    let ptr = mara.dynamic_new(0);
+    assert!(!ptr.is_null());
+    assert_eq!(ptr, mara.dynamic_new_zeroed(0));
+    // what a Vec of zero sized elements would do with a global allocator
+    let layout = Layout::new::<[u64; 0]>();
+    let global = unsafe { mara.alloc(layout) };
+    assert_eq!(global as usize % layout.align(), 0);
+    assert_eq!(mara.total_free_bytes(), free);
+    mara.dynamic_delete(ptr);
+    unsafe { mara.dealloc(global, layout) };
+    assert_eq!(mara.total_free_bytes(), free);
+    // growing an empty allocation takes a real block
+    let grown = mara.dynamic_resize(ptr, 32);
+    assert!(mara.usable_size(grown) >= 32);
+    mara.dynamic_delete(grown);
+    assert_eq!(mara.total_free_bytes(), free);
+    assert_eq!(mara.verify(), Ok(()));
+}
+
+#[test]
+fn test_every_zero_size_request_returns_the_same_pointer() {
+    use std::alloc::{GlobalAlloc, Layout};
+    let mut memory: Box<[u8]> = vec![0; MEMORY_SIZE].into_boxed_slice();
+    let mara = Mara::new(memory.as_mut_ptr(), MEMORY_SIZE);
+    let free = mara.total_free_bytes();
+    let blocks = mara.iter_blocks().count();
+    let ptr = mara.dynamic_new(0);
+    assert_eq!(ptr as usize % rusty_mara::MAX_ALIGN, 0);
+    assert_eq!(mara.try_dynamic_new(0), Ok(ptr));
+    assert_eq!(mara.dynamic_new_zeroed(0), ptr);
+    assert_eq!(mara.dynamic_new_bounded(0, 0), ptr);
+    let mut batch = [core::ptr::null_mut(); 4];
+    assert_eq!(mara.dynamic_new_batch(0, &mut batch), 4);
+    assert!(batch.iter().all(|&block| block == ptr));
+    let mut align = 1;
+    while align <= rusty_mara::MAX_ALIGN {
+        assert_eq!(mara.alloc_aligned_zeroed(0, align), ptr);
+        let layout = Layout::from_size_align(0, align).unwrap();
+        let slice = mara.allocate_slice(layout).unwrap();
+        assert_eq!(slice.as_ptr() as *mut u8, ptr);
+        assert_eq!(unsafe { slice.as_ref() }.len(), 0);
+        assert_eq!(unsafe { mara.alloc(layout) }, ptr);
+        assert_eq!(unsafe { mara.alloc_zeroed(layout) }, ptr);
+        align *= 2;
+    }
+    // larger alignments cannot be served
+    let too_large = 2 * rusty_mara::MAX_ALIGN;
+    assert!(mara.alloc_aligned_zeroed(0, too_large).is_null());
+    assert!(mara.alloc_aligned_zeroed(16, too_large).is_null());
+    let layout = Layout::from_size_align(0, too_large).unwrap();
+    assert_eq!(mara.allocate_slice(layout), None);
+    // the pointer can be freed any number of times
+    assert_eq!(mara.try_dynamic_delete(ptr), Ok(()));
+    assert_eq!(mara.try_dynamic_delete(ptr), Ok(()));
+    assert_eq!(mara.total_free_bytes(), free);
+    assert_eq!(mara.iter_blocks().count(), blocks);
+    assert_eq!(mara.verify(), Ok(()));
+}
+
+#[test]
+fn test_unsplit_remainder_is_part_of_the_block() {
+    let mut memory: Box<[u8]> = vec![0; MEMORY_SIZE].into_boxed_slice();
+    let mara = Mara::new(memory.as_mut_ptr(), MEMORY_SIZE);
+    let whole = mara.iter_free_spaces().next().unwrap().size;
+    // the remainder of two bytes cannot hold a free block
+    let requested = whole - 2;
+    let ptr = mara.dynamic_new_zeroed(requested);
+    assert!(!ptr.is_null());
+    let size = mara.usable_size(ptr);
+    assert!(size > requested);
+    assert_eq!(size, whole);
+    let block = mara.iter_blocks().find(|block| block.ptr == ptr).unwrap();
+    assert!(!block.free);
+    assert_eq!(block.size, size);
+    let space = unsafe { core::slice::from_raw_parts(ptr, size) };
+    assert!(space.iter().all(|&byte| byte == 0));
+    mara.dynamic_delete(ptr);
+    assert_eq!(mara.verify(), Ok(()));
+}
+
+#[test]
+fn test_compact_works_around_a_pinned_block() {
+    let mut memory: Box<[u8]> = vec![0; MEMORY_SIZE].into_boxed_slice();
+    // a single page, so the layout holds no free blocks of other pages
+    let mara = Mara::with_page_size(memory.as_mut_ptr(), MEMORY_SIZE, MEMORY_SIZE);
+    let blocks: Vec<*mut u8> = (0..20)
+        .map(|i| {
+            let ptr = mara.dynamic_new(32);
+            unsafe { core::ptr::write_bytes(ptr, i as u8, 32) };
+            ptr
+        })
+        .collect();
+    let pinned = blocks[11];
+    assert!(mara.pin(pinned));
+    for &ptr in blocks.iter().step_by(2) {
+        mara.dynamic_delete(ptr);
+    }
+    let mut new_ptr = core::ptr::null_mut();
+    assert!(!mara.relocate(pinned, &mut new_ptr));
+    let mut live: Vec<(*mut u8, u8)> = (1..20).step_by(2).map(|i| (blocks[i], i as u8)).collect();
+    mara.compact(|old, new| {
+        assert_ne!(old, pinned);
+        let block = live.iter_mut().find(|block| block.0 == old).unwrap();
+        block.0 = new;
+    });
+    assert_eq!(mara.verify(), Ok(()));
+    for &(ptr, content) in &live {
+        assert!(unsafe { core::slice::from_raw_parts(ptr, 32) }
+            .iter()
+            .all(|&byte| byte == content));
+    }
+    // the blocks in front of the pinned block are moved up to it, a free
+    // block remains in front of it and one at the end of the page
+    let layout: Vec<_> = mara.iter_blocks().collect();
+    let pinned_index = layout.iter().position(|block| block.ptr == pinned).unwrap();
+    assert!(layout[..pinned_index - 1].iter().all(|block| !block.free));
+    assert!(layout[pinned_index - 1].free);
+    assert!(layout[pinned_index + 1..layout.len() - 1]
+        .iter()
+        .all(|block| !block.free));
+    assert!(layout.last().unwrap().free);
+    // unpinned it moves into the gap
+    assert!(mara.unpin(pinned));
+    assert!(!mara.unpin(pinned));
+    let mut moved = Vec::new();
+    mara.compact(|old, new| moved.push((old, new)));
+    assert_eq!(moved.first().map(|&(old, _)| old), Some(pinned));
+    assert_eq!(mara.iter_blocks().filter(|block| block.free).count(), 1);
+    for (ptr, _) in live {
+        let ptr = moved
+            .iter()
+            .find(|&&(old, _)| old == ptr)
+            .map_or(ptr, |&(_, new)| new);
+        mara.dynamic_delete(ptr);
+    }
+    assert_eq!(mara.iter_blocks().count(), 1);
+}
+
+#[cfg(feature = "free_map")]
+#[test]
+fn test_free_map_blocks_up_to_127_bytes_have_single_byte_code_blocks() {
+    assert_eq!(Mara::overhead_for(127), 2);
+    assert_eq!(Mara::overhead_for(128), 4);
+    let mut memory: Box<[u8]> = vec![0; MEMORY_SIZE].into_boxed_slice();
+    let mara = Mara::new(memory.as_mut_ptr(), MEMORY_SIZE);
+    let blocks: Vec<*mut u8> = (0..8).map(|_| mara.dynamic_new(127)).collect();
+    for &ptr in blocks.iter().step_by(2) {
+        mara.dynamic_delete(ptr);
+    }
+    for (index, block) in mara.iter_blocks().take(blocks.len()).enumerate() {
+        assert_eq!(block.ptr, blocks[index]);
+        assert_eq!(block.code_block_size, 1);
+        assert_eq!(block.free, index % 2 == 0);
+    }
+    assert_eq!(mara.verify(), Ok(()));
+    // the freed blocks are found again by their free flags
+    let freed: Vec<*mut u8> = blocks.iter().step_by(2).copied().collect();
+    for _ in 0..freed.len() {
+        assert!(freed.contains(&mara.dynamic_new(127)));
+    }
+    for ptr in blocks {
+        mara.dynamic_delete(ptr);
+    }
+    assert_eq!(mara.iter_blocks().count(), mara.page_count());
+}
+
+#[test]
+fn test_global_alloc_keeps_the_alignment() {
+    use std::alloc::{GlobalAlloc, Layout};
+    let mut memory: Box<[u8]> = vec![0; MEMORY_SIZE].into_boxed_slice();
+    let mara = Mara::new(memory.as_mut_ptr(), MEMORY_SIZE);
+    // moves the following blocks off any alignment
+    mara.dynamic_new(7);
+    for &align in &[2, 4, 8, 16, 64] {
+        let layout = Layout::from_size_align(16, align).unwrap();
+        let ptr = unsafe { mara.alloc(layout) };
+        assert_eq!(ptr as usize % align, 0);
+        let zeroed = unsafe { mara.alloc_zeroed(layout) };
+        assert_eq!(zeroed as usize % align, 0);
+        assert!(unsafe { core::slice::from_raw_parts(zeroed, 16) }
+            .iter()
+            .all(|&byte| byte == 0));
+        unsafe { core::ptr::write_bytes(ptr, 0xA5, 16) };
+        // the zeroed block behind it prevents growing in place
+        let moved = unsafe { mara.realloc(ptr, layout, 200) };
+        assert_ne!(moved, ptr);
+        assert_eq!(moved as usize % align, 0);
+        assert_eq!(
+            unsafe { core::slice::from_raw_parts(moved, 16) },
+            &[0xA5; 16]
+        );
+        unsafe {
+            mara.dealloc(moved, Layout::from_size_align(200, align).unwrap());
+            mara.dealloc(zeroed, layout);
+        }
+    }
+    assert_eq!(mara.verify(), Ok(()));
+}
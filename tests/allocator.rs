@@ -0,0 +1,70 @@
+#![cfg(feature = "allocator_api")]
+#![feature(allocator_api)]
+
+extern crate rusty_mara;
+use rusty_mara::Mara;
+use std::alloc::{Allocator, Layout};
+
+const MEMORY_SIZE: usize = 0x1_0000;
+
+#[test]
+fn test_vec_in_mara() {
+    let mut memory: Box<[u8]> = vec![0; MEMORY_SIZE].into_boxed_slice();
+    let mara = Mara::new(memory.as_mut_ptr(), MEMORY_SIZE);
+    let mut vec: Vec<u64, &Mara> = Vec::new_in(&mara);
+    for value in 0..1000 {
+        vec.push(value);
+    }
+    assert!(vec.iter().copied().eq(0..1000));
+    assert!(mara.iter_blocks().any(|block| !block.free));
+    vec.shrink_to_fit();
+    assert_eq!(vec.iter().sum::<u64>(), 999 * 1000 / 2);
+    drop(vec);
+    assert!(!mara.iter_blocks().any(|block| !block.free));
+    assert_eq!(mara.verify(), Ok(()));
+}
+
+#[test]
+fn test_allocated_slice_has_the_usable_size() {
+    let mut memory: Box<[u8]> = vec![0; MEMORY_SIZE].into_boxed_slice();
+    let mara = Mara::new(memory.as_mut_ptr(), MEMORY_SIZE);
+    let layout = Layout::from_size_align(13, 8).unwrap();
+    let slice = (&mara).allocate(layout).unwrap();
+    let ptr = slice.as_ptr() as *mut u8;
+    assert_eq!(ptr as usize % 8, 0);
+    assert!(slice.len() >= 13);
+    assert_eq!(slice.len(), mara.usable_size(ptr));
+    unsafe { (&mara).deallocate(slice.cast(), layout) };
+    assert_eq!(mara.verify(), Ok(()));
+}
+
+#[test]
+fn test_empty_vec_does_not_allocate() {
+    let mut memory: Box<[u8]> = vec![0; MEMORY_SIZE].into_boxed_slice();
+    let mara = Mara::new(memory.as_mut_ptr(), MEMORY_SIZE);
+    let free = mara.total_free_bytes();
+    let empty: Vec<u8, &Mara> = Vec::new_in(&mara);
+    let units: Vec<(), &Mara> = Vec::with_capacity_in(100, &mara);
+    assert_eq!(mara.total_free_bytes(), free);
+    drop(empty);
+    drop(units);
+    assert_eq!(mara.total_free_bytes(), free);
+}
+
+#[test]
+fn test_zero_size_layouts_share_one_pointer() {
+    let mut memory: Box<[u8]> = vec![0; MEMORY_SIZE].into_boxed_slice();
+    let mara = Mara::new(memory.as_mut_ptr(), MEMORY_SIZE);
+    let free = mara.total_free_bytes();
+    let zero_size_block = mara.dynamic_new(0);
+    for &align in [1, 8, 64, rusty_mara::MAX_ALIGN].iter() {
+        let layout = Layout::from_size_align(0, align).unwrap();
+        let slice = (&mara).allocate(layout).unwrap();
+        assert_eq!(slice.as_ptr() as *mut u8, zero_size_block);
+        assert_eq!(slice.len(), 0);
+        unsafe { (&mara).deallocate(slice.cast(), layout) };
+    }
+    let layout = Layout::from_size_align(0, 2 * rusty_mara::MAX_ALIGN).unwrap();
+    assert!((&mara).allocate(layout).is_err());
+    assert_eq!(mara.total_free_bytes(), free);
+}
@@ -0,0 +1,162 @@
+extern crate rusty_mara;
+use rusty_mara::{validate_code_blocks, Mara};
+
+const MEMORY_SIZE: usize = 0x1000;
+
+#[test]
+fn test_neighbor_blocks_match_layout() {
+    let mut memory: Box<[u8]> = vec![0; MEMORY_SIZE].into_boxed_slice();
+    let mara = Mara::new(memory.as_mut_ptr(), MEMORY_SIZE);
+    let first = mara.dynamic_new(16);
+    let second = mara.dynamic_new(32);
+    let third = mara.dynamic_new(48);
+    mara.dynamic_delete(first);
+
+    let (left, right) = mara.neighbor_blocks(second);
+    let left = left.expect("second block has a left neighbor");
+    let right = right.expect("second block has a right neighbor");
+    assert_eq!(left.ptr, first);
+    assert_eq!(left.size, 16);
+    assert!(left.free);
+    assert_eq!(right.ptr, third);
+    assert_eq!(right.size, 48);
+    assert!(!right.free);
+    // single byte code blocks are enough for these sizes
+    assert_eq!(left.code_block_size, 1);
+    assert_eq!(second as usize, left.ptr as usize + left.size + 2);
+    assert_eq!(right.ptr as usize, second as usize + 32 + 2);
+
+    // the third block is followed by the free rest of the page
+    let (left, right) = mara.neighbor_blocks(third);
+    assert_eq!(left.unwrap().ptr, second);
+    let rest = right.unwrap();
+    assert!(rest.free);
+    assert!(rest.size > MEMORY_SIZE / 2);
+    assert_eq!(mara.neighbor_blocks(rest.ptr).1, None);
+    // the first block is the first one in the page
+    assert_eq!(mara.neighbor_blocks(first).0, None);
+}
+
+#[test]
+fn test_free_spaces_match_physical_free_blocks() {
+    // the 40 blocks do not fit in a page of MEMORY_SIZE next to the page struct
+    const LARGE_MEMORY_SIZE: usize = 2 * MEMORY_SIZE;
+    let mut memory: Box<[u8]> = vec![0; LARGE_MEMORY_SIZE].into_boxed_slice();
+    let mara = Mara::new(memory.as_mut_ptr(), LARGE_MEMORY_SIZE);
+    let blocks: Vec<*mut u8> = (0..40).map(|i| mara.dynamic_new(8 + i * 4)).collect();
+    for ptr in blocks.iter().step_by(3) {
+        mara.dynamic_delete(*ptr);
+    }
+    let mut from_buckets: Vec<_> = mara.iter_free_spaces().collect();
+    from_buckets.sort_by_key(|block| block.ptr as usize);
+    let physical: Vec<_> = mara.iter_blocks().filter(|block| block.free).collect();
+    // 13 holes and the last block that merged with the rest of the page
+    assert_eq!(physical.len(), 14);
+    assert_eq!(from_buckets, physical);
+}
+
+#[test]
+fn test_total_free_bytes() {
+    let mut memory: Box<[u8]> = vec![0; MEMORY_SIZE].into_boxed_slice();
+    let mara = Mara::new(memory.as_mut_ptr(), MEMORY_SIZE);
+    let empty = mara.total_free_bytes();
+    assert_eq!(empty, mara.largest_free_block());
+    let blocks: Vec<*mut u8> = (0..10).map(|_| mara.dynamic_new(40)).collect();
+    let rest = mara.total_free_bytes();
+    // every block takes its space and two single byte code blocks
+    assert_eq!(rest, empty - 10 * (40 + 2));
+    for ptr in blocks.iter().step_by(2) {
+        mara.dynamic_delete(*ptr);
+    }
+    // five separate holes, the largest free block is the rest of the page
+    assert_eq!(mara.total_free_bytes(), rest + 5 * 40);
+    assert_eq!(mara.largest_free_block(), rest);
+    let physical: usize = mara
+        .iter_blocks()
+        .filter(|block| block.free)
+        .map(|block| block.size)
+        .sum();
+    assert_eq!(mara.total_free_bytes(), physical);
+}
+
+#[test]
+fn test_fragmentation() {
+    let mut memory: Box<[u8]> = vec![0; MEMORY_SIZE].into_boxed_slice();
+    let mara = Mara::new(memory.as_mut_ptr(), MEMORY_SIZE);
+    assert_eq!(mara.fragmentation(), 0.0);
+    let mut blocks = Vec::new();
+    while let Ok(ptr) = mara.try_dynamic_new(40) {
+        blocks.push(ptr);
+    }
+    // use up the rest of the page
+    let rest = mara.largest_free_block();
+    if rest > 0 {
+        mara.dynamic_new(rest);
+    }
+    assert_eq!(mara.total_free_bytes(), 0);
+    assert_eq!(mara.fragmentation(), 0.0);
+    // every other block is free, the holes cannot be merged
+    let holes: Vec<usize> = blocks
+        .iter()
+        .step_by(2)
+        .map(|&ptr| mara.usable_size(ptr))
+        .collect();
+    for ptr in blocks.iter().step_by(2) {
+        mara.dynamic_delete(*ptr);
+    }
+    // the last block might have taken a rest that was too small to split off
+    let total: usize = holes.iter().sum();
+    assert_eq!(mara.total_free_bytes(), total);
+    let largest = *holes.iter().max().unwrap();
+    assert_eq!(mara.largest_free_block(), largest);
+    let expected = 1.0 - largest as f64 / total as f64;
+    assert!((mara.fragmentation() - expected).abs() < 1e-9);
+}
+
+#[cfg(not(feature = "no_std"))]
+#[test]
+fn test_snapshot_diff() {
+    let mut memory: Box<[u8]> = vec![0; MEMORY_SIZE].into_boxed_slice();
+    let mara = Mara::new(memory.as_mut_ptr(), MEMORY_SIZE);
+    let kept = mara.dynamic_new(32);
+    let freed = mara.dynamic_new(48);
+    let before = mara.snapshot();
+    assert_eq!(before.used_bytes, 32 + 48);
+
+    let allocated = mara.dynamic_new(24);
+    mara.dynamic_delete(freed);
+    let after = mara.snapshot();
+    let diff = before.diff(&after);
+    assert_eq!(diff.allocated.len(), 1);
+    assert_eq!(diff.allocated[0].ptr, allocated);
+    assert_eq!(diff.allocated[0].size, 24);
+    assert_eq!(diff.freed.len(), 1);
+    assert_eq!(diff.freed[0].ptr, freed);
+    assert!(diff.resized.is_empty());
+    assert!(after.blocks.iter().any(|block| block.ptr == kept));
+    // nothing happened in between
+    assert_eq!(after.diff(&mara.snapshot()), Default::default());
+}
+
+#[test]
+fn test_validate_code_blocks_finds_the_corrupted_block() {
+    let mut memory: Box<[u8]> = vec![0; MEMORY_SIZE].into_boxed_slice();
+    let mara = Mara::new(memory.as_mut_ptr(), MEMORY_SIZE);
+    let blocks: Vec<*mut u8> = (0..4).map(|_| mara.dynamic_new(16)).collect();
+    mara.dynamic_delete(blocks[0]);
+    let first_corrupted = || {
+        mara.iter_blocks()
+            .find(|block| unsafe {
+                validate_code_blocks(block.ptr.sub(block.code_block_size)).is_err()
+            })
+            .map(|block| block.ptr)
+    };
+    assert_eq!(first_corrupted(), None);
+    // an overflow by one byte hits the right code block
+    let overflow = unsafe { blocks[2].add(mara.usable_size(blocks[2])) };
+    let original = unsafe { *overflow };
+    unsafe { *overflow = 0 };
+    assert_eq!(first_corrupted(), Some(blocks[2]));
+    unsafe { *overflow = original };
+    assert_eq!(first_corrupted(), None);
+}
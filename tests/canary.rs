@@ -0,0 +1,21 @@
+#![cfg(feature = "canary")]
+
+extern crate rusty_mara;
+use rusty_mara::Mara;
+
+const MEMORY_SIZE: usize = 0x1000;
+
+#[test]
+#[should_panic(expected = "canary")]
+fn test_overwritten_page_struct_is_detected() {
+    let mut memory: Box<[u8]> = vec![0; MEMORY_SIZE].into_boxed_slice();
+    let mara = Mara::new(memory.as_mut_ptr(), MEMORY_SIZE);
+    let first = mara.dynamic_new(16);
+    // the page struct lies in front of the first block and its code block
+    let page_struct_size = first as usize - memory.as_ptr() as usize - 1;
+    mara.dynamic_delete(first);
+    for byte in &mut memory[..page_struct_size] {
+        *byte = 0x41;
+    }
+    mara.dynamic_new(16);
+}
@@ -20,3 +20,51 @@ fn test_long() {
         .build();
     test.run();
 }
+
+#[test]
+fn test_report_peaks() {
+    use rusty_mara::TestBuilder;
+    const MEMORY_SIZE: usize = 0x10_0000;
+    let mut memory: Box<[u8]> = vec![0; MEMORY_SIZE].into_boxed_slice();
+    // sizes 5 to 7 are rounded up to 8 and nothing is freed
+    let report = TestBuilder::new((*memory).as_mut_ptr(), MEMORY_SIZE)
+        .amount_new_variables(100)
+        .max_iterations(0)
+        .p_free(0.0)
+        .min_size(5)
+        .max_size(8)
+        .build()
+        .run();
+    assert_eq!(report.dynamic_blocks_peak, 101);
+    assert_eq!(report.dynamic_memory_peak, 101 * 8);
+    assert_eq!(report.static_memory_peak, 0);
+    assert_eq!(report.static_blocks_peak, 0);
+}
+
+#[test]
+fn test_insertion_policies() {
+    use rusty_mara::{InsertionPolicy, TestBuilder};
+    const MEMORY_SIZE: usize = 0x400_0000;
+    println!("policy\tmilliseconds\tlargestFreeBlock\tfragmentation");
+    for &policy in &[InsertionPolicy::Lifo, InsertionPolicy::Fifo] {
+        let mut memory: Box<[u8]> = vec![0; MEMORY_SIZE].into_boxed_slice();
+        let mut test = TestBuilder::new((*memory).as_mut_ptr(), MEMORY_SIZE)
+            .amount_new_variables(20_000)
+            .insertion_policy(policy)
+            .build();
+        let begin = std::time::Instant::now();
+        let report = test.run();
+        let elapsed = begin.elapsed();
+        let mara = test.mara();
+        println!(
+            "{:?}\t{}\t{}\t{:.3}",
+            policy,
+            elapsed.as_millis(),
+            mara.largest_free_block(),
+            mara.fragmentation()
+        );
+        assert_eq!(report.corrupted_blocks, 0);
+        assert_eq!(report.free_space_not_in_bucket_list, 0);
+        assert_eq!(mara.verify(), Ok(()));
+    }
+}
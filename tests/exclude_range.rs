@@ -0,0 +1,151 @@
+extern crate rusty_mara;
+use rusty_mara::{Mara, MaraError};
+
+// a single page, even with 16 bit next pointers
+const MEMORY_SIZE: usize = 0xF000;
+const EXCLUDED_OFFSET: usize = 0x1000;
+const EXCLUDED_LEN: usize = 100;
+const MARKER: u8 = 0xEE;
+
+fn overlaps(ptr: *mut u8, size: usize, start: *mut u8, len: usize) -> bool {
+    (ptr as usize) < start as usize + len && (start as usize) < ptr as usize + size
+}
+
+#[test]
+fn test_excluded_range_is_never_allocated() {
+    let mut memory: Box<[u8]> = vec![0; MEMORY_SIZE].into_boxed_slice();
+    let start = unsafe { memory.as_mut_ptr().add(EXCLUDED_OFFSET) };
+    let mara = Mara::with_page_size(memory.as_mut_ptr(), MEMORY_SIZE, MEMORY_SIZE);
+    mara.exclude_range(start, EXCLUDED_LEN);
+    unsafe { core::ptr::write_bytes(start, MARKER, EXCLUDED_LEN) };
+
+    // allocate well past the excluded range
+    let mut pointers = Vec::new();
+    for _ in 0..400 {
+        let ptr = mara.dynamic_new(32);
+        assert!(!overlaps(ptr, 32, start, EXCLUDED_LEN));
+        unsafe { core::ptr::write_bytes(ptr, 0xAB, 32) };
+        pointers.push(ptr);
+    }
+    // free everything so the neighbors of the range coalesce
+    for ptr in pointers {
+        mara.dynamic_delete(ptr);
+    }
+    for i in 0..EXCLUDED_LEN {
+        assert_eq!(unsafe { *start.add(i) }, MARKER);
+    }
+    // the memory in front of the range is too small, so a large block
+    // has to be placed behind the range
+    let large = mara.dynamic_new(2 * EXCLUDED_OFFSET);
+    assert!(large as usize > start as usize + EXCLUDED_LEN);
+    unsafe { core::ptr::write_bytes(large, 0xAB, 2 * EXCLUDED_OFFSET) };
+    let small = mara.dynamic_new(EXCLUDED_OFFSET / 2);
+    assert!(!overlaps(small, EXCLUDED_OFFSET / 2, start, EXCLUDED_LEN));
+    for i in 0..EXCLUDED_LEN {
+        assert_eq!(unsafe { *start.add(i) }, MARKER);
+    }
+}
+
+#[test]
+#[should_panic]
+fn test_exclude_range_outside_of_memory() {
+    let mut memory: Box<[u8]> = vec![0; MEMORY_SIZE].into_boxed_slice();
+    let mara = Mara::with_page_size(memory.as_mut_ptr(), MEMORY_SIZE, MEMORY_SIZE);
+    let start = unsafe { memory.as_mut_ptr().add(MEMORY_SIZE - 10) };
+    mara.exclude_range(start, 20);
+}
+
+/// The used block whose space holds the excluded range
+fn excluded_block(mara: &Mara, start: *mut u8) -> *mut u8 {
+    mara.iter_blocks()
+        .find(|block| overlaps(block.ptr, block.size, start, EXCLUDED_LEN))
+        .map(|block| {
+            assert!(!block.free);
+            block.ptr
+        })
+        .unwrap()
+}
+
+/// Allocates blocks of 32 bytes until the heap is full
+fn fill(mara: &Mara) -> Vec<*mut u8> {
+    core::iter::from_fn(|| Some(mara.dynamic_new(32)))
+        .take_while(|ptr| !ptr.is_null())
+        .collect()
+}
+
+#[test]
+fn test_excluded_range_cannot_be_freed() {
+    let mut memory: Box<[u8]> = vec![0; MEMORY_SIZE].into_boxed_slice();
+    let start = unsafe { memory.as_mut_ptr().add(EXCLUDED_OFFSET) };
+    let mara = Mara::with_page_size(memory.as_mut_ptr(), MEMORY_SIZE, MEMORY_SIZE);
+    mara.exclude_range(start, EXCLUDED_LEN);
+    let excluded = excluded_block(&mara, start);
+    assert!(!mara.owns(excluded));
+    assert_eq!(
+        mara.try_dynamic_delete(excluded),
+        Err(MaraError::InvalidFree)
+    );
+    let mut new_ptr = core::ptr::null_mut();
+    assert!(!mara.relocate(excluded, &mut new_ptr));
+    assert_eq!(excluded_block(&mara, start), excluded);
+    assert_eq!(mara.verify(), Ok(()));
+}
+
+#[test]
+fn test_compact_keeps_the_excluded_range() {
+    let mut memory: Box<[u8]> = vec![0; MEMORY_SIZE].into_boxed_slice();
+    let start = unsafe { memory.as_mut_ptr().add(EXCLUDED_OFFSET) };
+    let mara = Mara::with_page_size(memory.as_mut_ptr(), MEMORY_SIZE, MEMORY_SIZE);
+    mara.exclude_range(start, EXCLUDED_LEN);
+    unsafe { core::ptr::write_bytes(start, MARKER, EXCLUDED_LEN) };
+    let excluded = excluded_block(&mara, start);
+    let mut pointers = fill(&mara);
+    // free every other block on both sides of the range
+    for ptr in pointers.iter_mut().step_by(2) {
+        mara.dynamic_delete(*ptr);
+        *ptr = core::ptr::null_mut();
+    }
+    pointers.retain(|ptr| !ptr.is_null());
+    for &ptr in pointers.iter() {
+        unsafe { (ptr as *mut usize).write_unaligned(ptr as usize) };
+    }
+    let mut moved = Vec::new();
+    mara.compact(|old, new| moved.push((old, new)));
+    assert!(!moved.is_empty());
+    assert_eq!(excluded_block(&mara, start), excluded);
+    for &(old, new) in moved.iter() {
+        assert!(!overlaps(new, 32, start, EXCLUDED_LEN));
+        assert_eq!(unsafe { (new as *const usize).read_unaligned() }, old as usize);
+    }
+    for i in 0..EXCLUDED_LEN {
+        assert_eq!(unsafe { *start.add(i) }, MARKER);
+    }
+    // behind the range the free memory is a single block at the end
+    let behind: Vec<_> = mara
+        .iter_blocks()
+        .filter(|block| block.ptr as usize > start as usize)
+        .collect();
+    assert!(behind[..behind.len() - 1].iter().all(|block| !block.free));
+    assert!(behind.last().unwrap().free);
+    assert_eq!(mara.verify(), Ok(()));
+}
+
+#[test]
+fn test_reset_keeps_the_excluded_range() {
+    let mut memory: Box<[u8]> = vec![0; MEMORY_SIZE].into_boxed_slice();
+    let start = unsafe { memory.as_mut_ptr().add(EXCLUDED_OFFSET) };
+    let mara = Mara::with_page_size(memory.as_mut_ptr(), MEMORY_SIZE, MEMORY_SIZE);
+    mara.exclude_range(start, EXCLUDED_LEN);
+    unsafe { core::ptr::write_bytes(start, MARKER, EXCLUDED_LEN) };
+    let before: Vec<_> = mara.iter_blocks().collect();
+    fill(&mara);
+    mara.reset();
+    assert_eq!(mara.iter_blocks().collect::<Vec<_>>(), before);
+    for ptr in fill(&mara) {
+        assert!(!overlaps(ptr, 32, start, EXCLUDED_LEN));
+    }
+    for i in 0..EXCLUDED_LEN {
+        assert_eq!(unsafe { *start.add(i) }, MARKER);
+    }
+    assert_eq!(mara.verify(), Ok(()));
+}
@@ -0,0 +1,58 @@
+extern crate rusty_mara;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use rusty_mara::Mara;
+
+const MEMORY_SIZE: usize = 0x1000;
+
+static LIVE_BLOCKS: AtomicUsize = AtomicUsize::new(0);
+static LIVE_BYTES: AtomicUsize = AtomicUsize::new(0);
+
+fn record_alloc(_ptr: *mut u8, size: usize) {
+    LIVE_BLOCKS.fetch_add(1, Ordering::Relaxed);
+    LIVE_BYTES.fetch_add(size, Ordering::Relaxed);
+}
+
+fn record_free(_ptr: *mut u8, size: usize) {
+    LIVE_BLOCKS.fetch_sub(1, Ordering::Relaxed);
+    LIVE_BYTES.fetch_sub(size, Ordering::Relaxed);
+}
+
+fn live() -> (usize, usize) {
+    (
+        LIVE_BLOCKS.load(Ordering::Relaxed),
+        LIVE_BYTES.load(Ordering::Relaxed),
+    )
+}
+
+#[test]
+fn test_observers_see_every_block() {
+    let mut memory: Box<[u8]> = vec![0; MEMORY_SIZE].into_boxed_slice();
+    let mara = Mara::new(memory.as_mut_ptr(), MEMORY_SIZE);
+    mara.on_alloc(Some(record_alloc));
+    mara.on_free(Some(record_free));
+    let first = mara.dynamic_new(16);
+    let second = mara.dynamic_new(40);
+    assert_eq!(live(), (2, 56));
+    // moved blocks are reported as a new block and a free
+    let moved = mara.dynamic_resize(first, 200);
+    assert_ne!(moved, first);
+    assert_eq!(live(), (2, 240));
+    mara.dynamic_delete(second);
+    mara.dynamic_delete(moved);
+    assert_eq!(live(), (0, 0));
+    // blocks resized in place are reported with their new size
+    let block = mara.dynamic_new(16);
+    assert_eq!(mara.dynamic_resize(block, 40), block);
+    assert_eq!(live(), (1, mara.usable_size(block)));
+    assert_eq!(mara.dynamic_resize(block, 16), block);
+    assert_eq!(live(), (1, mara.usable_size(block)));
+    mara.dynamic_delete(block);
+    assert_eq!(live(), (0, 0));
+    // removed callbacks are not called anymore
+    mara.on_alloc(None);
+    mara.on_free(None);
+    let block = mara.dynamic_new(16);
+    mara.dynamic_delete(block);
+    mara.dynamic_new(16);
+    assert_eq!(live(), (0, 0));
+}
@@ -0,0 +1,45 @@
+// the limit differs with 16 or 64 bit next pointers,
+// poison would write the whole lazily mapped page and the free map
+// takes a part of it
+#![cfg(not(any(
+    feature = "bit16",
+    feature = "bit64",
+    feature = "poison",
+    feature = "free_map"
+)))]
+
+extern crate rusty_mara;
+use rusty_mara::Mara;
+
+/// Offsets in a page are stored in a u32 and the page needs room for
+/// the two code blocks of its largest block.
+/// Sizes close to 2³² are encoded in 5 byte code blocks.
+const LIMIT: usize = u32::MAX as usize - 2 * 5;
+
+#[test]
+fn test_page_at_offset_limit() {
+    // zeroed memory is mapped lazily, only the touched parts get committed
+    let mut memory: Box<[u8]> = vec![0; LIMIT].into_boxed_slice();
+    let mara = Mara::with_page_size(memory.as_mut_ptr(), LIMIT, LIMIT);
+    // leave a small free space at the very end of the page
+    let large = mara.dynamic_new(LIMIT - 0x1000);
+    assert!(!large.is_null());
+    // its next pointer offset is close to the limit
+    let small = mara.dynamic_new(64);
+    assert!(small as usize > large as usize);
+    assert!((small as usize) < memory.as_ptr() as usize + LIMIT);
+    unsafe { core::ptr::write_bytes(small, 0xAB, 64) };
+    mara.dynamic_delete(large);
+    mara.dynamic_delete(small);
+    // everything merged again
+    let large = mara.dynamic_new(LIMIT - 0x1000);
+    assert!(!large.is_null());
+    mara.dynamic_delete(large);
+}
+
+#[test]
+#[should_panic(expected = "Pages greater than")]
+fn test_page_one_byte_over_offset_limit() {
+    let mut memory: Box<[u8]> = vec![0; LIMIT + 1].into_boxed_slice();
+    Mara::with_page_size(memory.as_mut_ptr(), LIMIT + 1, LIMIT + 1);
+}
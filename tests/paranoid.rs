@@ -0,0 +1,44 @@
+#![cfg(feature = "paranoid")]
+
+extern crate rusty_mara;
+use rusty_mara::Mara;
+
+const MEMORY_SIZE: usize = 0x1000;
+
+#[test]
+fn test_verified_operations() {
+    let mut memory: Box<[u8]> = vec![0; MEMORY_SIZE].into_boxed_slice();
+    let mara = Mara::new(memory.as_mut_ptr(), MEMORY_SIZE);
+    let blocks: Vec<*mut u8> = (0..20).map(|i| mara.dynamic_new(8 + i * 4)).collect();
+    for ptr in blocks.into_iter().step_by(2) {
+        mara.dynamic_delete(ptr);
+    }
+    let ptr = mara.alloc_aligned_zeroed(64, 32);
+    mara.dynamic_delete(ptr);
+}
+
+#[test]
+#[should_panic(expected = "already free")]
+fn test_double_free_is_reported_by_the_second_free() {
+    let mut memory: Box<[u8]> = vec![0; MEMORY_SIZE].into_boxed_slice();
+    let mara = Mara::new(memory.as_mut_ptr(), MEMORY_SIZE);
+    let ptr = mara.dynamic_new(32);
+    // keep the freed block from merging with the rest of the page
+    mara.dynamic_new(32);
+    mara.dynamic_delete(ptr);
+    // refused before the heap is touched
+    mara.dynamic_delete(ptr);
+}
+
+#[test]
+#[should_panic(expected = "heap is corrupted before dynamic_new(16)")]
+fn test_use_after_free_is_reported_by_the_next_operation() {
+    let mut memory: Box<[u8]> = vec![0; MEMORY_SIZE].into_boxed_slice();
+    let mara = Mara::new(memory.as_mut_ptr(), MEMORY_SIZE);
+    let ptr = mara.dynamic_new(32);
+    mara.dynamic_new(32);
+    mara.dynamic_delete(ptr);
+    // overwrite the next pointer of the free space
+    unsafe { core::ptr::write_bytes(ptr, 0xAB, 4) };
+    mara.dynamic_new(16);
+}
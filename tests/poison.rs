@@ -0,0 +1,71 @@
+#![cfg(feature = "poison")]
+
+extern crate rusty_mara;
+use rusty_mara::{Mara, SMALLEST_POSSIBLE_SPACE};
+
+const MEMORY_SIZE: usize = 0x1000;
+const POISON_BYTE: u8 = 0xDE;
+
+#[test]
+fn test_freed_block_is_poisoned_behind_the_next_pointer() {
+    let mut memory: Box<[u8]> = vec![0; MEMORY_SIZE].into_boxed_slice();
+    let mara = Mara::new(memory.as_mut_ptr(), MEMORY_SIZE);
+    let _left = mara.dynamic_new(16);
+    let block = mara.dynamic_new(32);
+    let _right = mara.dynamic_new(16);
+    unsafe { core::ptr::write_bytes(block, 0x41, 32) };
+    mara.dynamic_delete(block);
+    let content = unsafe { core::slice::from_raw_parts(block, 32) };
+    assert!(content[SMALLEST_POSSIBLE_SPACE..]
+        .iter()
+        .all(|&byte| byte == POISON_BYTE));
+    assert_eq!(mara.verify(), Ok(()));
+    // the intact block is handed out again
+    assert_eq!(mara.dynamic_new(32), block);
+}
+
+#[test]
+#[should_panic(expected = "after it was freed")]
+fn test_write_after_free_is_detected() {
+    let mut memory: Box<[u8]> = vec![0; MEMORY_SIZE].into_boxed_slice();
+    let mara = Mara::new(memory.as_mut_ptr(), MEMORY_SIZE);
+    let _left = mara.dynamic_new(16);
+    let block = mara.dynamic_new(32);
+    let _right = mara.dynamic_new(16);
+    mara.dynamic_delete(block);
+    unsafe { *block.add(20) = 0x41 };
+    mara.dynamic_new(32);
+}
+
+#[test]
+fn test_configured_poison_byte() {
+    const OWN_POISON_BYTE: u8 = 0xA5;
+    let mut memory: Box<[u8]> = vec![0; MEMORY_SIZE].into_boxed_slice();
+    let mara = Mara::with_poison_byte(memory.as_mut_ptr(), MEMORY_SIZE, OWN_POISON_BYTE);
+    // the free space of the new page is checked against the configured byte
+    let _left = mara.dynamic_new(16);
+    let block = mara.dynamic_new(32);
+    let _right = mara.dynamic_new(16);
+    unsafe { core::ptr::write_bytes(block, 0x41, 32) };
+    mara.dynamic_delete(block);
+    let content = unsafe { core::slice::from_raw_parts(block, 32) };
+    assert!(content[SMALLEST_POSSIBLE_SPACE..]
+        .iter()
+        .all(|&byte| byte == OWN_POISON_BYTE));
+    assert_eq!(mara.verify(), Ok(()));
+    assert_eq!(mara.dynamic_new(32), block);
+}
+
+#[test]
+#[should_panic(expected = "after it was freed")]
+fn test_write_after_free_is_detected_with_a_configured_poison_byte() {
+    let mut memory: Box<[u8]> = vec![0; MEMORY_SIZE].into_boxed_slice();
+    let mara = Mara::with_poison_byte(memory.as_mut_ptr(), MEMORY_SIZE, 0xA5);
+    let _left = mara.dynamic_new(16);
+    let block = mara.dynamic_new(32);
+    let _right = mara.dynamic_new(16);
+    mara.dynamic_delete(block);
+    // the default poison byte is a write now
+    unsafe { *block.add(20) = 0xDE };
+    mara.dynamic_new(32);
+}
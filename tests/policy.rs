@@ -0,0 +1,198 @@
+extern crate rusty_mara;
+use rusty_mara::{AllocStrategy, FitPolicy, InsertionPolicy, Mara, PagePolicy};
+
+const MEMORY_SIZE: usize = 0xF000; // below the page size limit of 16 bit next pointers
+
+/// Frees three separated blocks of the same size in order and returns them
+fn free_in_order(mara: &Mara) -> Vec<*mut u8> {
+    let blocks: Vec<*mut u8> = (0..6).map(|_| mara.dynamic_new(32)).collect();
+    let freed: Vec<*mut u8> = blocks.into_iter().step_by(2).collect();
+    for ptr in &freed {
+        mara.dynamic_delete(*ptr);
+    }
+    freed
+}
+
+#[test]
+fn test_lifo_uses_newest_free_block() {
+    let mut memory: Box<[u8]> = vec![0; MEMORY_SIZE].into_boxed_slice();
+    let mara = Mara::new(memory.as_mut_ptr(), MEMORY_SIZE);
+    let freed = free_in_order(&mara);
+    assert_eq!(mara.dynamic_new(32), freed[2]);
+    assert_eq!(mara.dynamic_new(32), freed[1]);
+    assert_eq!(mara.dynamic_new(32), freed[0]);
+}
+
+#[test]
+fn test_fifo_uses_oldest_free_block() {
+    let mut memory: Box<[u8]> = vec![0; MEMORY_SIZE].into_boxed_slice();
+    let mara = Mara::new(memory.as_mut_ptr(), MEMORY_SIZE);
+    mara.set_insertion_policy(InsertionPolicy::Fifo);
+    let freed = free_in_order(&mara);
+    assert_eq!(mara.verify(), Ok(()));
+    assert_eq!(mara.dynamic_new(32), freed[0]);
+    assert_eq!(mara.dynamic_new(32), freed[1]);
+    // blocks freed later are queued behind the remaining one
+    mara.dynamic_delete(freed[0]);
+    assert_eq!(mara.dynamic_new(32), freed[2]);
+    assert_eq!(mara.dynamic_new(32), freed[0]);
+    assert_eq!(mara.verify(), Ok(()));
+}
+
+#[test]
+fn test_fifo_keeps_heap_consistent() {
+    let mut memory: Box<[u8]> = vec![0; MEMORY_SIZE].into_boxed_slice();
+    let mara = Mara::new(memory.as_mut_ptr(), MEMORY_SIZE);
+    mara.set_insertion_policy(InsertionPolicy::Fifo);
+    let mut blocks = Vec::new();
+    for round in 0..20 {
+        for i in 0..20 {
+            blocks.push(mara.dynamic_new(8 + (round * 7 + i * 13) % 200));
+        }
+        // free every third block, including the ones at the end of a bucket
+        let mut i = 0;
+        blocks.retain(|ptr| {
+            i += 1;
+            if i % 3 == 0 {
+                mara.dynamic_delete(*ptr);
+                false
+            } else {
+                true
+            }
+        });
+        assert_eq!(mara.verify(), Ok(()));
+    }
+    for ptr in blocks {
+        mara.dynamic_delete(ptr);
+    }
+    assert_eq!(mara.verify(), Ok(()));
+    assert_eq!(mara.iter_blocks().count(), mara.page_count());
+}
+
+#[test]
+fn test_small_blocks_are_not_coalesced() {
+    let mut memory: Box<[u8]> = vec![0; MEMORY_SIZE].into_boxed_slice();
+    let mara = Mara::with_coalesce_threshold(memory.as_mut_ptr(), MEMORY_SIZE, 64);
+    let small: Vec<*mut u8> = (0..3).map(|_| mara.dynamic_new(16)).collect();
+    let large: Vec<*mut u8> = (0..2).map(|_| mara.dynamic_new(100)).collect();
+    mara.dynamic_new(16);
+    for ptr in small.iter().chain(large.iter()) {
+        mara.dynamic_delete(*ptr);
+    }
+    // the small blocks stay side by side with their size
+    assert_eq!(mara.usable_size(small[0]), 16);
+    assert_eq!(mara.usable_size(small[1]), 16);
+    let (_, right) = mara.neighbor_blocks(small[0]);
+    assert_eq!(right.unwrap().ptr, small[1]);
+    assert!(right.unwrap().free);
+    // the large blocks are merged with each other and the last small block
+    let merged = mara.neighbor_blocks(small[1]).1.unwrap();
+    assert!(merged.free);
+    assert!(merged.size > 16 + 2 * 100);
+    assert_eq!(mara.verify(), Ok(()));
+    // a freed small block is reused for its size
+    assert_eq!(mara.dynamic_new(16), small[1]);
+}
+
+#[test]
+fn test_switch_from_first_fit_to_best_fit() {
+    let mut memory: Box<[u8]> = vec![0; MEMORY_SIZE].into_boxed_slice();
+    let mara = Mara::new(memory.as_mut_ptr(), MEMORY_SIZE);
+    assert_eq!(mara.strategy(), AllocStrategy::default());
+    // two holes of the same bucket, the larger one is freed last
+    assert_eq!(Mara::bucket_index(52), Mara::bucket_index(60));
+    let small = mara.dynamic_new(52);
+    let _separator = mara.dynamic_new(8);
+    let large = mara.dynamic_new(60);
+    let _separator = mara.dynamic_new(8);
+    let free_holes = || {
+        mara.dynamic_delete(small);
+        mara.dynamic_delete(large);
+    };
+    free_holes();
+    // first fit takes the head of the bucket
+    assert_eq!(mara.dynamic_new(50), large);
+    assert_eq!(mara.dynamic_new(50), small);
+    free_holes();
+    let best_fit = AllocStrategy {
+        fit: FitPolicy::BestFit,
+        ..AllocStrategy::default()
+    };
+    mara.set_strategy(best_fit);
+    assert_eq!(mara.strategy(), best_fit);
+    assert_eq!(mara.verify(), Ok(()));
+    assert_eq!(mara.dynamic_new(50), small);
+    assert_eq!(mara.dynamic_new(50), large);
+    assert_eq!(mara.verify(), Ok(()));
+}
+
+/// The size of the free block that was split off behind ``ptr``
+fn leftover(mara: &Mara, ptr: *mut u8) -> usize {
+    let right = mara.neighbor_blocks(ptr).1.unwrap();
+    assert!(right.free);
+    right.size
+}
+
+#[test]
+fn test_best_fit_leaves_a_smaller_leftover() {
+    let mut leftovers = Vec::new();
+    for &fit_policy in &[FitPolicy::FirstFit, FitPolicy::BestFit] {
+        let mut memory: Box<[u8]> = vec![0; MEMORY_SIZE].into_boxed_slice();
+        let mara = Mara::new(memory.as_mut_ptr(), MEMORY_SIZE);
+        mara.set_fit_policy(fit_policy);
+        // the larger hole is at the head of the bucket
+        let small = mara.dynamic_new(52);
+        let _separator = mara.dynamic_new(8);
+        let large = mara.dynamic_new(60);
+        let _separator = mara.dynamic_new(8);
+        mara.dynamic_delete(small);
+        mara.dynamic_delete(large);
+        let ptr = mara.dynamic_new(40);
+        leftovers.push(leftover(&mara, ptr));
+        assert_eq!(mara.verify(), Ok(()));
+    }
+    assert!(leftovers[1] < leftovers[0]);
+}
+
+#[test]
+fn test_address_ordered_uses_lowest_free_block() {
+    let mut memory: Box<[u8]> = vec![0; MEMORY_SIZE].into_boxed_slice();
+    let mara = Mara::new(memory.as_mut_ptr(), MEMORY_SIZE);
+    mara.set_insertion_policy(InsertionPolicy::AddressOrdered);
+    let blocks: Vec<*mut u8> = (0..10).map(|_| mara.dynamic_new(32)).collect();
+    // the new head, the new tail and the middle of the bucket
+    for &index in &[4, 0, 8, 6, 2] {
+        mara.dynamic_delete(blocks[index]);
+    }
+    assert_eq!(mara.verify(), Ok(()));
+    // a merge removes a space from the middle of the bucket
+    mara.dynamic_delete(blocks[5]);
+    mara.dynamic_delete(blocks[3]);
+    assert_eq!(mara.verify(), Ok(()));
+    assert_eq!(mara.dynamic_new(32), blocks[0]);
+    assert_eq!(mara.dynamic_new(32), blocks[8]);
+    assert_eq!(mara.verify(), Ok(()));
+}
+
+#[test]
+fn test_most_full_packs_blocks_into_one_page() {
+    const PAGE_SIZE: usize = 0x1000;
+    let mut memory: Box<[u8]> = vec![0; 4 * PAGE_SIZE].into_boxed_slice();
+    let start = memory.as_ptr() as usize;
+    let page_index = |ptr: *mut u8| (ptr as usize - start) / PAGE_SIZE;
+    let mara = Mara::with_page_size(memory.as_mut_ptr(), 4 * PAGE_SIZE, PAGE_SIZE);
+    // one block in every page
+    mara.set_page_policy(PagePolicy::LeastFull);
+    let spread: Vec<*mut u8> = (0..4).map(|_| mara.dynamic_new(64)).collect();
+    let spread_pages: Vec<usize> = spread.iter().map(|&ptr| page_index(ptr)).collect();
+    assert_eq!(spread_pages, vec![0, 1, 2, 3]);
+    // the first page is empty again, but the others are fuller
+    mara.dynamic_delete(spread[0]);
+    mara.set_page_policy(PagePolicy::MostFull);
+    let pages: Vec<usize> = (0..80).map(|_| page_index(mara.dynamic_new(64))).collect();
+    let first_spill = pages.iter().position(|&page| page != 1).unwrap();
+    // the page header and the code blocks take the rest of the page
+    assert!(first_spill * 64 > PAGE_SIZE * 2 / 3);
+    assert!(pages[first_spill..].iter().all(|&page| page == 2));
+    assert_eq!(mara.verify(), Ok(()));
+}
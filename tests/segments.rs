@@ -0,0 +1,168 @@
+extern crate rusty_mara;
+use rusty_mara::Mara;
+
+const SEGMENT_SIZE: usize = 0x1000;
+
+fn in_segment(ptr: *mut u8, segment: &[u8]) -> bool {
+    let start = segment.as_ptr() as usize;
+    start <= ptr as usize && (ptr as usize) < start + segment.len()
+}
+
+#[test]
+fn test_two_segments() {
+    let mut internal: Box<[u8]> = vec![0; SEGMENT_SIZE].into_boxed_slice();
+    let mut external: Box<[u8]> = vec![0; 2 * SEGMENT_SIZE].into_boxed_slice();
+    let mara = Mara::from_segments(&[
+        (internal.as_mut_ptr(), SEGMENT_SIZE),
+        (external.as_mut_ptr(), 2 * SEGMENT_SIZE),
+    ]);
+    // too large for the first segment
+    let large = mara.dynamic_new(SEGMENT_SIZE + 100);
+    assert!(in_segment(large, &external));
+    // the first segment is used until it is full
+    let blocks: Vec<*mut u8> = (0..60).map(|_| mara.dynamic_new(64)).collect();
+    assert!(in_segment(blocks[0], &internal));
+    assert!(in_segment(blocks[59], &external));
+    assert!(blocks
+        .iter()
+        .all(|&ptr| in_segment(ptr, &internal) || in_segment(ptr, &external)));
+    for (i, &ptr) in blocks.iter().enumerate() {
+        unsafe { core::ptr::write_bytes(ptr, i as u8, 64) };
+    }
+    // frees are routed to the segment of the pointer
+    for &ptr in blocks.iter().step_by(2) {
+        mara.dynamic_delete(ptr);
+    }
+    assert_eq!(mara.verify(), Ok(()));
+    for &ptr in blocks.iter().skip(1).step_by(2) {
+        mara.dynamic_delete(ptr);
+    }
+    mara.dynamic_delete(large);
+    assert_eq!(mara.verify(), Ok(()));
+    // one free block per segment
+    assert_eq!(mara.iter_blocks().count(), 2);
+    assert!(mara.iter_blocks().all(|block| block.free));
+}
+
+#[test]
+fn test_new_auto_splits_large_regions() {
+    #[cfg(not(feature = "bit16"))]
+    const REGION_SIZE: usize = 0x100_0000; // 16 MiB
+    // every page is 32 KiB large with 16 bit next pointers
+    #[cfg(feature = "bit16")]
+    const REGION_SIZE: usize = 0x8_0000; // 512 KiB
+    let mut region: Box<[u8]> = vec![0; REGION_SIZE].into_boxed_slice();
+    let mara = Mara::new_auto(region.as_mut_ptr(), REGION_SIZE);
+    assert_eq!(Mara::auto_page_size(REGION_SIZE), REGION_SIZE / 16);
+    assert_eq!(mara.page_count(), 16);
+    // a block has to fit in one page
+    let max_allocation = mara.max_allocation_size();
+    #[cfg(not(feature = "free_map"))]
+    let overhead = 0x400;
+    // one free flag for every SMALLEST_POSSIBLE_FREE_SPACE bytes
+    #[cfg(feature = "free_map")]
+    let overhead = 0x400 + REGION_SIZE / 16 / (8 * rusty_mara::SMALLEST_POSSIBLE_FREE_SPACE);
+    assert!(max_allocation < REGION_SIZE / 16);
+    assert!(max_allocation > REGION_SIZE / 16 - overhead);
+    let blocks: Vec<*mut u8> = (0..20).map(|_| mara.dynamic_new(max_allocation)).collect();
+    assert!(blocks[..16].iter().all(|ptr| !ptr.is_null()));
+    assert!(blocks[16..].iter().all(|ptr| ptr.is_null()));
+    assert!(blocks[..16].iter().all(|&ptr| in_segment(ptr, &region)));
+    assert_eq!(mara.verify(), Ok(()));
+
+    // small regions stay a single page
+    let mara = Mara::new_auto(region.as_mut_ptr(), 0x8000);
+    assert_eq!(mara.page_count(), 1);
+    // uneven splits leave no small rest
+    let mara = Mara::with_page_size(region.as_mut_ptr(), 3 * 0x1000 + 10, 0x1000);
+    assert_eq!(mara.page_count(), 4);
+    assert!(mara.max_allocation_size() > 0x0C00 - 0x400);
+}
+
+#[test]
+fn test_fragmented_page_falls_through_to_the_next_page() {
+    const REGION_SIZE: usize = 4 * SEGMENT_SIZE;
+    let mut region: Box<[u8]> = vec![0; REGION_SIZE].into_boxed_slice();
+    let mara = Mara::with_page_size(region.as_mut_ptr(), REGION_SIZE, SEGMENT_SIZE);
+    let first_page = &region[..SEGMENT_SIZE];
+    let mut blocks = Vec::new();
+    loop {
+        let ptr = mara.dynamic_new(64);
+        if !in_segment(ptr, first_page) {
+            mara.dynamic_delete(ptr);
+            break;
+        }
+        blocks.push(ptr);
+    }
+    // half of the first page is free, but only in small pieces
+    for &ptr in blocks.iter().step_by(2) {
+        mara.dynamic_delete(ptr);
+    }
+    let large = mara.dynamic_new(SEGMENT_SIZE / 4);
+    assert!(!large.is_null());
+    assert!(!in_segment(large, first_page));
+    // frees find the owning page in the ring
+    mara.dynamic_delete(large);
+    for &ptr in blocks.iter().skip(1).step_by(2) {
+        mara.dynamic_delete(ptr);
+    }
+    assert_eq!(mara.verify(), Ok(()));
+    assert_eq!(mara.iter_blocks().count(), 4);
+}
+
+#[test]
+fn test_free_across_two_pages_in_reverse_order() {
+    let mut first: Box<[u8]> = vec![0; SEGMENT_SIZE].into_boxed_slice();
+    let mut second: Box<[u8]> = vec![0; SEGMENT_SIZE].into_boxed_slice();
+    let mara = Mara::from_segments(&[
+        (first.as_mut_ptr(), SEGMENT_SIZE),
+        (second.as_mut_ptr(), SEGMENT_SIZE),
+    ]);
+    // fill the first page and put two blocks in the second one
+    let mut blocks = vec![mara.dynamic_new(100)];
+    while !in_segment(blocks[blocks.len() - 1], &second) {
+        blocks.push(mara.dynamic_new(100));
+    }
+    blocks.push(mara.dynamic_new(100));
+    for &ptr in blocks.iter().rev() {
+        assert_eq!(mara.try_dynamic_delete(ptr), Ok(()));
+    }
+    assert_eq!(mara.verify(), Ok(()));
+    assert_eq!(mara.iter_blocks().count(), 2);
+    assert!(mara.iter_blocks().all(|block| block.free));
+}
+
+#[test]
+#[should_panic(expected = "page size")]
+fn test_page_size_larger_than_the_region() {
+    let mut region: Box<[u8]> = vec![0; SEGMENT_SIZE].into_boxed_slice();
+    Mara::with_page_size(region.as_mut_ptr(), SEGMENT_SIZE, 2 * SEGMENT_SIZE);
+}
+
+#[test]
+#[should_panic(expected = "Mara: a page needs at least")]
+fn test_page_size_too_small_for_a_page() {
+    let mut region: Box<[u8]> = vec![0; SEGMENT_SIZE].into_boxed_slice();
+    Mara::with_page_size(region.as_mut_ptr(), SEGMENT_SIZE, 64);
+}
+
+#[test]
+fn test_smallest_page_size() {
+    let page_size = Mara::min_page_size();
+    let mut region: Box<[u8]> = vec![0; 2 * page_size].into_boxed_slice();
+    let mara = Mara::with_page_size(region.as_mut_ptr(), 2 * page_size, page_size);
+    assert_eq!(mara.page_count(), 2);
+    assert_eq!(mara.verify(), Ok(()));
+    let ptr = mara.dynamic_new(1);
+    assert!(!ptr.is_null());
+    mara.dynamic_delete(ptr);
+    assert_eq!(mara.verify(), Ok(()));
+}
+
+#[test]
+#[should_panic(expected = "Mara: a page needs at least")]
+fn test_segment_below_the_smallest_page_size() {
+    let size = Mara::min_page_size() - 1;
+    let mut segment: Box<[u8]> = vec![0; size].into_boxed_slice();
+    Mara::from_segments(&[(segment.as_mut_ptr(), size)]);
+}
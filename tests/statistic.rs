@@ -0,0 +1,180 @@
+#![cfg(feature = "statistic")]
+
+extern crate rusty_mara;
+use core::time::Duration;
+use rusty_mara::{Mara, SearchStats, TimingBreakdown};
+
+const MEMORY_SIZE: usize = 0x10_0000;
+
+/// Allocates ``count`` blocks of ``size`` bytes and frees every other one,
+/// so the freed blocks can not be merged.
+fn fragment(mara: &Mara, size: usize, count: usize) {
+    let blocks: Vec<*mut u8> = (0..count).map(|_| mara.dynamic_new(size)).collect();
+    for ptr in blocks.into_iter().skip(1).step_by(2) {
+        mara.dynamic_delete(ptr);
+    }
+}
+
+/// average steps of the searches between ``before`` and ``after``
+fn average_since(before: SearchStats, after: SearchStats) -> f64 {
+    (after.total_steps - before.total_steps) as f64 / (after.searches - before.searches) as f64
+}
+
+#[test]
+fn test_search_steps_for_a_single_size() {
+    let mut memory: Box<[u8]> = vec![0; MEMORY_SIZE].into_boxed_slice();
+    let mara = Mara::new(memory.as_mut_ptr(), MEMORY_SIZE);
+    fragment(&mara, 16, 200);
+    let before = mara.search_stats();
+    assert_eq!(before.searches, 200);
+    // the requests find a hole of their size right away
+    for _ in 0..100 {
+        mara.dynamic_new(16);
+    }
+    let after = mara.search_stats();
+    assert_eq!(after.searches, 300);
+    // only the last request has to search the buckets for larger spaces
+    assert!(average_since(before, after) < 1.5);
+}
+
+#[test]
+fn test_search_steps_rise_with_fragmentation() {
+    let mut memory: Box<[u8]> = vec![0; MEMORY_SIZE].into_boxed_slice();
+    let mara = Mara::new(memory.as_mut_ptr(), MEMORY_SIZE);
+    // 17 and 20 byte spaces share a bucket, so 20 byte requests have to
+    // walk past all the 17 byte holes
+    fragment(&mara, 17, 200);
+    let before = mara.search_stats();
+    for _ in 0..10 {
+        mara.dynamic_new(20);
+    }
+    let after = mara.search_stats();
+    assert!(average_since(before, after) > 50.0);
+    assert!(after.max_steps > 50);
+}
+
+#[test]
+fn test_reset_statistics_keeps_live_state() {
+    let mut memory: Box<[u8]> = vec![0; MEMORY_SIZE].into_boxed_slice();
+    let mara = Mara::new(memory.as_mut_ptr(), MEMORY_SIZE);
+    let blocks: Vec<*mut u8> = (0..10).map(|_| mara.dynamic_new(64)).collect();
+    for ptr in &blocks[5..] {
+        mara.dynamic_delete(*ptr);
+    }
+    let statistics = mara.statistics();
+    assert_eq!(statistics.allocations, 10);
+    assert_eq!(statistics.frees, 5);
+    assert_eq!(statistics.allocated_bytes, 5 * 64);
+    assert_eq!(statistics.peak_allocated_bytes, 10 * 64);
+
+    mara.reset_statistics();
+    let statistics = mara.statistics();
+    assert_eq!(statistics.allocations, 0);
+    assert_eq!(statistics.frees, 0);
+    assert_eq!(statistics.internal_waste, 0);
+    assert_eq!(statistics.search.searches, 0);
+    assert_eq!(statistics.search.total_steps, 0);
+    assert_eq!(statistics.allocated_bytes, 5 * 64);
+    assert_eq!(statistics.peak_allocated_bytes, 10 * 64);
+
+    mara.reset_peaks();
+    let statistics = mara.statistics();
+    assert_eq!(statistics.peak_allocated_bytes, 5 * 64);
+    assert_eq!(statistics.search.max_steps, 0);
+    // counting continues after the reset
+    mara.dynamic_delete(blocks[0]);
+    assert_eq!(mara.statistics().frees, 1);
+    assert_eq!(mara.statistics().allocated_bytes, 4 * 64);
+}
+
+#[test]
+fn test_max_chain_lengths() {
+    let mut memory: Box<[u8]> = vec![0; MEMORY_SIZE].into_boxed_slice();
+    let mara = Mara::new(memory.as_mut_ptr(), MEMORY_SIZE);
+    let initial = mara.max_chain_lengths();
+    let last_bucket = initial.len() - 1;
+    // only the free rest of the page is in the largest bucket
+    assert_eq!(initial[last_bucket], 1);
+    // 99 holes, the last freed block merges with the rest of the page
+    fragment(&mara, 16, 200);
+    let lengths = mara.max_chain_lengths();
+    let bucket = (0..last_bucket).find(|b| lengths[*b] != 0).unwrap();
+    assert_eq!(lengths[bucket], 99);
+    for (index, length) in lengths.iter().enumerate() {
+        if index != bucket && index != last_bucket {
+            assert_eq!(*length, 0);
+        }
+    }
+    // the maximum stays when the holes are used again
+    for _ in 0..99 {
+        mara.dynamic_new(16);
+    }
+    assert_eq!(mara.max_chain_lengths()[bucket], 99);
+    mara.reset_peaks();
+    assert_eq!(mara.max_chain_lengths()[bucket], 0);
+}
+
+#[test]
+fn test_timing_of_a_coalescing_workload() {
+    let mut memory: Box<[u8]> = vec![0; MEMORY_SIZE].into_boxed_slice();
+    let mara = Mara::new(memory.as_mut_ptr(), MEMORY_SIZE);
+    let blocks: Vec<*mut u8> = (0..1000).map(|_| mara.dynamic_new(16)).collect();
+    let after_allocation = mara.timing_breakdown();
+    assert!(after_allocation.search > Duration::default());
+    // every free merges with the block in front of it
+    for ptr in blocks {
+        mara.dynamic_delete(ptr);
+    }
+    let timing = mara.timing_breakdown();
+    let merge = timing.merge - after_allocation.merge;
+    let bucket_update = timing.bucket_update - after_allocation.bucket_update;
+    let search = timing.search - after_allocation.search;
+    assert!(merge > Duration::default());
+    // the bucket updates of the frees are part of the merges
+    assert!(merge >= bucket_update);
+    assert_eq!(search, Duration::default());
+    mara.reset_statistics();
+    assert_eq!(mara.timing_breakdown(), TimingBreakdown::default());
+}
+
+#[test]
+fn test_touched_buckets() {
+    let mut memory: Box<[u8]> = vec![0; MEMORY_SIZE].into_boxed_slice();
+    let mara = Mara::new(memory.as_mut_ptr(), MEMORY_SIZE);
+    assert_eq!(mara.touched_buckets(), 0);
+    let sizes = [8, 16, 100, 500];
+    let blocks: Vec<*mut u8> = sizes.iter().map(|size| mara.dynamic_new(*size)).collect();
+    for ptr in blocks {
+        mara.dynamic_delete(ptr);
+    }
+    let expected = sizes
+        .iter()
+        .fold(0, |mask, size| mask | 1 << Mara::bucket_index(*size));
+    // the splits and merges of the free spaces do not count
+    assert_eq!(mara.touched_buckets(), expected);
+    assert_eq!(mara.touched_buckets().count_ones(), 4);
+    // the mask survives a reset of the statistics
+    mara.reset_statistics();
+    assert_eq!(mara.touched_buckets(), expected);
+}
+
+#[test]
+fn test_live_blocks_and_peaks() {
+    let mut memory: Box<[u8]> = vec![0; MEMORY_SIZE].into_boxed_slice();
+    let mara = Mara::new(memory.as_mut_ptr(), MEMORY_SIZE);
+    let blocks: Vec<*mut u8> = (0..10).map(|_| mara.dynamic_new(64)).collect();
+    for &ptr in &blocks[..6] {
+        mara.dynamic_delete(ptr);
+    }
+    let statistics = mara.statistics();
+    assert_eq!(statistics.allocations, 10);
+    assert_eq!(statistics.frees, 6);
+    assert_eq!(statistics.live_blocks, 4);
+    assert_eq!(statistics.peak_live_blocks, 10);
+    assert_eq!(statistics.allocated_bytes, 4 * 64);
+    assert!(statistics.peak_allocated_bytes > statistics.allocated_bytes);
+    mara.reset_peaks();
+    assert_eq!(mara.statistics().peak_live_blocks, 4);
+    mara.reset();
+    assert_eq!(mara.statistics().live_blocks, 0);
+}
@@ -0,0 +1,62 @@
+#![cfg(feature = "sync")]
+
+extern crate rusty_mara;
+use rusty_mara::Mara;
+
+const MEMORY_SIZE: usize = 0x10_0000;
+
+#[test]
+fn test_allocations_from_several_threads() {
+    let mut memory: Box<[u8]> = vec![0; MEMORY_SIZE].into_boxed_slice();
+    let mara = Mara::new(memory.as_mut_ptr(), MEMORY_SIZE);
+    std::thread::scope(|scope| {
+        for thread in 0..4u8 {
+            let mara = &mara;
+            scope.spawn(move || {
+                for round in 0..1000 {
+                    let size = 8 + (round % 16) * 4;
+                    let ptrs: Vec<*mut u8> = (0..8).map(|_| mara.dynamic_new(size)).collect();
+                    for ptr in &ptrs {
+                        unsafe { core::ptr::write_bytes(*ptr, thread, size) };
+                    }
+                    for ptr in ptrs {
+                        let content = unsafe { core::slice::from_raw_parts(ptr, size) };
+                        // no other thread got the same block
+                        assert!(content.iter().all(|&byte| byte == thread));
+                        mara.dynamic_delete(ptr);
+                    }
+                }
+            });
+        }
+    });
+    assert_eq!(mara.iter_blocks().count(), mara.page_count());
+    assert_eq!(mara.verify(), Ok(()));
+}
+
+#[test]
+fn test_inspection_while_other_threads_allocate() {
+    let mut memory: Box<[u8]> = vec![0; MEMORY_SIZE].into_boxed_slice();
+    let mara = Mara::new(memory.as_mut_ptr(), MEMORY_SIZE);
+    let done = std::sync::atomic::AtomicBool::new(false);
+    std::thread::scope(|scope| {
+        for _ in 0..2 {
+            let (mara, done) = (&mara, &done);
+            scope.spawn(move || {
+                for round in 0..2000 {
+                    let ptr = mara.dynamic_new(8 + round % 64);
+                    mara.dynamic_delete(ptr);
+                }
+                done.store(true, std::sync::atomic::Ordering::Relaxed);
+            });
+        }
+        // every inspection sees a consistent heap
+        while !done.load(std::sync::atomic::Ordering::Relaxed) {
+            assert_eq!(mara.verify(), Ok(()));
+            let free: usize = mara.iter_free_spaces().map(|block| block.size).sum();
+            assert!(free >= mara.largest_free_block());
+            assert!(mara.iter_blocks().count() >= 1);
+            assert!((0.0..1.0).contains(&mara.fragmentation()));
+        }
+    });
+    assert_eq!(mara.iter_blocks().count(), mara.page_count());
+}